@@ -1,17 +1,34 @@
 use core::f64;
-use std::collections::HashMap;
+use std::{
+    collections::{BTreeSet, HashMap},
+    path::PathBuf,
+    process::ExitStatus,
+    time::{Instant, SystemTime},
+};
 
 use crossterm::event::KeyCode;
-use once_cell::sync::Lazy;
 use ratatui::widgets::ListState;
 use regex::Regex;
 
-use crate::{ui::UiState, utils::RingBuffer};
+use crate::{
+    export,
+    input::LineSource,
+    log_view::{prompt_regex, LogView, PromptKind},
+    parser::MetricParser,
+    smoothing::Smoothing,
+    snapshot::{self, DEFAULT_SNAPSHOT_PATH, DEFAULT_SNAPSHOT_SIZE},
+    ui::UiState,
+    utils::{strip_timestamp, RingBuffer},
+    view::StepView,
+};
 
 type Entry = (f64, f64);
 
 pub struct Timeseries {
+    /// Values as parsed from the input, smoothing is only applied for display
     data_points: Vec<Entry>,
+    /// Wall-clock time each data point was read
+    timestamps: Vec<SystemTime>,
     min_val: f64,
     max_val: f64,
 }
@@ -21,6 +38,10 @@ impl Timeseries {
         self.data_points.len()
     }
 
+    pub fn is_empty(&self) -> bool {
+        self.data_points.is_empty()
+    }
+
     pub fn get_min(&self) -> f64 {
         self.min_val
     }
@@ -32,12 +53,62 @@ impl Timeseries {
     pub fn get_data(&self) -> &Vec<Entry> {
         &self.data_points
     }
+
+    pub fn get_timestamps(&self) -> &[SystemTime] {
+        &self.timestamps
+    }
+
+    /// Appends a data point that was read at `timestamp`
+    pub fn push(&mut self, t: f64, val: f64, timestamp: SystemTime) {
+        self.min_val = f64::min(self.min_val, val);
+        self.max_val = f64::max(self.max_val, val);
+        self.data_points.push((t, val));
+        self.timestamps.push(timestamp);
+    }
+
+    /// Returns the data points smoothed for display
+    pub fn smoothed(&self, smoothing: &Smoothing) -> Vec<Entry> {
+        let values: Vec<f64> = self.data_points.iter().map(|(_, val)| *val).collect();
+        self.data_points
+            .iter()
+            .zip(smoothing.apply(&values))
+            .map(|((t, _), val)| (*t, val))
+            .collect()
+    }
+
+    /// Returns the index of the data point closest to step `t`
+    pub fn nearest(&self, t: f64) -> Option<usize> {
+        self.data_points
+            .iter()
+            .enumerate()
+            .min_by(|(_, (t0, _)), (_, (t1, _))| (t0 - t).abs().total_cmp(&(t1 - t).abs()))
+            .map(|(idx, _)| idx)
+    }
+
+    /// Returns the min and max value of all data points with `start <= t <= end`
+    pub fn get_min_max_in(&self, start: f64, end: f64) -> Option<(f64, f64)> {
+        self.data_points
+            .iter()
+            .filter(|(t, _)| (start..=end).contains(t))
+            .fold(None, |bounds, (_, val)| match bounds {
+                None => Some((*val, *val)),
+                Some((min, max)) => Some((f64::min(min, *val), f64::max(max, *val))),
+            })
+    }
+
+    /// Returns the x values of the first and the last data point
+    pub fn get_t_bounds(&self) -> Option<(f64, f64)> {
+        let (first, _) = self.data_points.first()?;
+        let (last, _) = self.data_points.last()?;
+        Some((*first, *last))
+    }
 }
 
 impl Default for Timeseries {
     fn default() -> Self {
         Self {
             data_points: Vec::new(),
+            timestamps: Vec::new(),
             min_val: f64::INFINITY,
             max_val: f64::NEG_INFINITY,
         }
@@ -46,22 +117,93 @@ impl Default for Timeseries {
 
 pub struct AppState {
     pub data: HashMap<String, Timeseries>,
-    pub display_key: Option<String>,
+    /// Keys of all series plotted in one chart
+    pub display_keys: Vec<String>,
+    /// Keys toggled in the key selection dialog
+    pub marked_keys: BTreeSet<String>,
     pub ui_state: UiState,
     pub selection_list_state: ListState,
-    pub ema_factor: f64,
-    pub linebuf: RingBuffer<String>,
+    /// Index of the selected tile in the grid view
+    pub grid_selected: usize,
+    /// Number of tile columns of the last rendered grid
+    pub grid_columns: usize,
+    /// Number of tiles per page of the last rendered grid
+    pub grid_page_size: usize,
+    /// Whether charts use a logarithmic y-axis unless toggled otherwise
+    pub log_scale: bool,
+    /// Charts whose y-axis scale was toggled, keyed by their first series
+    pub log_scale_overrides: HashMap<String, bool>,
+    /// Visible part of the step axis in the plot view
+    pub step_view: StepView,
+    /// Index of the data point of the first displayed series under the cursor
+    pub cursor: Option<usize>,
+    /// Whether the statistics of the plotted series are shown next to the chart
+    pub stats_panel: bool,
+    /// Number of last values the mean, standard deviation and slope of the panel use
+    pub stats_window: usize,
+    /// Metrics that are maximized, instead of guessing it from their names
+    pub maximize: Option<Regex>,
+    /// Smoothing applied for display
+    pub smoothing: Smoothing,
+    /// Whether the input is a file that is followed as it grows
+    pub follow: bool,
+    /// Time the last line was read
+    pub last_input: Option<Instant>,
+    /// Number of lines read from the input
+    pub lines_read: usize,
+    /// Number of JSON lines and messages that are invalid or have no numeric value.
+    /// Other lines without metrics, e.g. plain log messages, aren't counted
+    pub parse_failures: usize,
+    /// Time the app was started
+    pub started_at: Instant,
+    /// Time the input reached its end
+    pub finished_at: Option<Instant>,
+    /// Exit status of the command whose output is read
+    pub exit_status: Option<ExitStatus>,
+    /// Message shown for a few seconds, e.g. the result of an export, and when it was set
+    pub notice: Option<(Instant, String)>,
+    /// Last step of each series restored from a session, values of the input
+    /// up to this step are duplicates and skipped for the whole session
+    pub resumed_steps: HashMap<String, f64>,
+    /// Names of the runs if there are several inputs. The keys of their series
+    /// end with `@run`, e.g. `loss@baseline`
+    pub runs: Vec<String>,
+    pub linebuf: RingBuffer<(LineSource, String)>,
+    /// Scroll position, search and filter of the passthrough view
+    pub log_view: LogView,
 }
 
 impl Default for AppState {
     fn default() -> Self {
         Self {
             data: HashMap::new(),
-            display_key: None,
+            display_keys: Vec::new(),
+            marked_keys: BTreeSet::new(),
             ui_state: UiState::Plot,
             selection_list_state: ListState::default().with_selected(Some(0)),
-            ema_factor: 1.0,
+            grid_selected: 0,
+            grid_columns: 1,
+            grid_page_size: 1,
+            log_scale: false,
+            log_scale_overrides: HashMap::new(),
+            step_view: StepView::default(),
+            cursor: None,
+            stats_panel: false,
+            stats_window: 10,
+            maximize: None,
+            smoothing: Smoothing::default(),
+            follow: false,
+            last_input: None,
+            lines_read: 0,
+            parse_failures: 0,
+            started_at: Instant::now(),
+            finished_at: None,
+            exit_status: None,
+            notice: None,
+            resumed_steps: HashMap::new(),
+            runs: Vec::new(),
             linebuf: RingBuffer::new(10),
+            log_view: LogView::default(),
         }
     }
 }
@@ -71,29 +213,102 @@ impl AppState {
         Self::default()
     }
 
-    fn insert(&mut self, key: &str, new_val: f64) {
-        let new_t = self.max_t(key);
+    /// Inserts a value at step `t`, or after the last value of the series if `t` is `None`
+    ///
+    /// Values at steps up to the last step restored from a session are skipped,
+    /// they were read before already. This holds even after later steps were read,
+    /// so the steps of a resumed series keep increasing.
+    fn insert(&mut self, key: &str, t: Option<f64>, new_val: f64) {
+        if let (Some(t), Some(last)) = (t, self.resumed_steps.get(key)) {
+            if t <= *last {
+                return;
+            }
+        }
+
+        let new_t = t.unwrap_or_else(|| self.next_t(key));
         let entry = self.data.entry(key.into()).or_default();
+        entry.push(new_t, new_val, SystemTime::now());
+    }
 
-        let new_val = match entry.data_points.last() {
-            Some((_, val)) => self.ema_factor * new_val + (1.0 - self.ema_factor) * val,
-            None => new_val,
-        };
+    /// Returns all keys in alphabetical order
+    pub fn sorted_keys(&self) -> Vec<String> {
+        let mut keys: Vec<String> = self.data.keys().cloned().collect();
+        keys.sort();
+        keys
+    }
 
-        entry.min_val = f64::min(entry.min_val, new_val);
-        entry.max_val = f64::max(entry.max_val, new_val);
+    /// Returns the key of `metric` of the run with index `run`
+    pub fn series_key(&self, run: usize, metric: &str) -> String {
+        match self.runs.get(run) {
+            Some(name) if self.runs.len() > 1 => format!("{metric}@{name}"),
+            _ => metric.to_owned(),
+        }
+    }
 
-        entry.data_points.push((new_t, new_val));
+    /// Splits `key` into its metric and the name of its run, see [`AppState::series_key`]
+    ///
+    /// Run names never contain `@`, so the metric keeps any `@` of its own.
+    pub fn split_key<'a>(&self, key: &'a str) -> (&'a str, Option<&'a str>) {
+        if self.runs.len() > 1 {
+            if let Some((metric, run)) = key.rsplit_once('@') {
+                if self.runs.iter().any(|name| name == run) {
+                    return (metric, Some(run));
+                }
+            }
+        }
+        (key, None)
     }
 
-    pub fn max_t(&self, key: &str) -> f64 {
-        let Some(data) = self.data.get(key.into()) else {
-            return 0.0;
-        };
-        data.len() as f64
+    /// Returns the keys of the plotted series
+    ///
+    /// With several runs, the metric of each displayed series is overlaid for
+    /// all runs, directly after the displayed series.
+    pub fn plotted_keys(&self) -> Vec<String> {
+        if self.runs.len() <= 1 {
+            return self.display_keys.clone();
+        }
+
+        let mut keys: Vec<String> = Vec::new();
+        for key in &self.display_keys {
+            let (metric, _) = self.split_key(key);
+            let runs = (0..self.runs.len()).map(|run| self.series_key(run, metric));
+            for key in std::iter::once(key.clone()).chain(runs) {
+                if self.data.contains_key(&key) && !keys.contains(&key) {
+                    keys.push(key);
+                }
+            }
+        }
+        keys
+    }
+
+    /// Returns the union of the step ranges of all plotted series
+    pub fn display_t_bounds(&self) -> Option<(f64, f64)> {
+        self.plotted_keys()
+            .iter()
+            .filter_map(|key| self.data.get(key)?.get_t_bounds())
+            .reduce(|(first, last), (t0, t1)| (f64::min(first, t0), f64::max(last, t1)))
+    }
+
+    /// Returns whether the chart of `key` uses a logarithmic y-axis
+    pub fn is_log_scale(&self, key: &str) -> bool {
+        self.log_scale_overrides
+            .get(key)
+            .copied()
+            .unwrap_or(self.log_scale)
+    }
+
+    /// Returns the x value of a value of `key` without a step, one after its last value
+    ///
+    /// Series without explicit steps are at `0, 1, 2, ...`, so this is their point count.
+    pub fn next_t(&self, key: &str) -> f64 {
+        let last = self.data.get(key).and_then(|data| data.get_data().last());
+        last.map_or(0.0, |(t, _)| t + 1.0)
     }
 }
 
+/// File written by the export hotkey if `--export` isn't given
+pub const DEFAULT_EXPORT_PATH: &str = "ezboard-export.csv";
+
 /// Application
 /// Contains all app state and logic
 /// Shouldn't contain any gui related functions and state
@@ -102,6 +317,14 @@ pub struct App {
     pub running: bool,
     /// Contains the whole state of the app (buffered lines, parsed values, etc)
     pub state: AppState,
+    /// Extracts metric values from input lines
+    pub parser: MetricParser,
+    /// File written by the export hotkey, CSV or JSON depending on the extension
+    pub export_path: PathBuf,
+    /// File written by the snapshot hotkey, see [`snapshot::write`]
+    pub snapshot_path: PathBuf,
+    /// Size of snapshots in terminal cells
+    pub snapshot_size: (u16, u16),
 }
 
 impl Default for App {
@@ -109,6 +332,10 @@ impl Default for App {
         Self {
             running: true,
             state: AppState::default(),
+            parser: MetricParser::default(),
+            export_path: PathBuf::from(DEFAULT_EXPORT_PATH),
+            snapshot_path: PathBuf::from(DEFAULT_SNAPSHOT_PATH),
+            snapshot_size: DEFAULT_SNAPSHOT_SIZE,
         }
     }
 }
@@ -119,9 +346,16 @@ impl App {
             running: true,
             state: AppState {
                 linebuf: RingBuffer::new(line_buffer_length),
-                ema_factor,
+                smoothing: Smoothing {
+                    ema_factor,
+                    ..Default::default()
+                },
                 ..Default::default()
             },
+            parser: MetricParser::default(),
+            export_path: PathBuf::from(DEFAULT_EXPORT_PATH),
+            snapshot_path: PathBuf::from(DEFAULT_SNAPSHOT_PATH),
+            snapshot_size: DEFAULT_SNAPSHOT_SIZE,
         }
     }
 
@@ -129,36 +363,123 @@ impl App {
         self.running = false;
     }
 
-    fn insert(&mut self, key: &str, new_val: f64) {
-        if self.state.data.len() == 0 {
-            self.state.display_key = Some(key.into());
+    /// Records that the input reached its end, and how the command exited if
+    /// the input is a child process
+    pub fn finish_input(&mut self, exit_status: Option<ExitStatus>) {
+        self.state.finished_at.get_or_insert_with(Instant::now);
+        self.state.exit_status = self.state.exit_status.or(exit_status);
+    }
+
+    /// Returns one line per series with its number of points, last, min and max value
+    pub fn summary(&self) -> String {
+        let mut summary = String::new();
+        for key in self.state.sorted_keys() {
+            let data = &self.state.data[&key];
+            let Some((t, last)) = data.get_data().last() else {
+                continue;
+            };
+            summary += &format!(
+                "{key}: {} points, last {last} at step {t}, min {}, max {}\n",
+                data.len(),
+                data.get_min(),
+                data.get_max()
+            );
         }
-        self.state.insert(key, new_val);
+        summary
+    }
+
+    fn insert(&mut self, key: &str, t: Option<f64>, new_val: f64) {
+        if self.state.data.is_empty() {
+            self.state.display_keys = vec![key.into()];
+        }
+        self.state.insert(key, t, new_val);
     }
 
     pub fn process_line(&mut self, line: &str) {
-        static PATTERN: Lazy<Regex> = Lazy::new(|| {
-            Regex::new(
-                r"(?i)(\b\w*?(?:loss|error|cost|acc|accuracy)\b)[\s--\n]*:?[\s--\n]*(-?[0-9]+(?:\.[0-9]+)?(?:e-?[0-9]+)?)",
-            )
-            .unwrap()
-        });
+        self.process_line_from(0, LineSource::Stdout, line);
+    }
 
-        self.state.linebuf.add(line.to_owned());
+    /// Processes a line of the run with index `run` written to `source`, metrics
+    /// are extracted from all sources
+    ///
+    /// A leading timestamp as written by `--tee-timestamps` is ignored.
+    pub fn process_line_from(&mut self, run: usize, source: LineSource, line: &str) {
+        self.state.linebuf.add((source, line.to_owned()));
+        self.state.log_view.line_added();
+        self.state.last_input = Some(Instant::now());
+        self.state.lines_read += 1;
+
+        let line = strip_timestamp(line);
+        if let Some(record) = self.parser.extract_json(line) {
+            if record.values.is_empty() {
+                self.state.parse_failures += 1;
+            }
+            for (key, val) in record.values {
+                let key = self.state.series_key(run, &key);
+                self.insert(&key, record.step, val);
+            }
+            return;
+        }
 
-        for (_, [key, val]) in PATTERN.captures_iter(&line).map(|c| c.extract()) {
-            let Ok(val) = val.parse() else { return };
+        let step = self.parser.extract_step(line);
+        let values = self.parser.extract(line);
+        // An object that isn't valid JSON, e.g. one cut off when the process was killed
+        if values.is_empty() && line.trim_start().starts_with('{') {
+            self.state.parse_failures += 1;
+        }
+        for (key, val) in values {
             let key = if line.to_lowercase().contains("test") {
-                key.to_owned() + "_test"
+                key + "_test"
             } else if line.to_lowercase().contains("val") {
-                key.to_owned() + "_val"
+                key + "_val"
             } else {
-                key.to_owned()
+                key
             };
-            self.insert(&key, val);
+            let key = self.state.series_key(run, &key);
+            self.insert(&key, step, val);
         }
     }
 
+    /// Processes a metric message received by a listener
+    ///
+    /// Messages aren't shown in the passthrough view. Invalid ones are reported as notice.
+    pub fn process_message(&mut self, message: &str) {
+        self.state.last_input = Some(Instant::now());
+        let record = self.parser.extract_message(message);
+        let Some(record) = record.filter(|record| !record.values.is_empty()) else {
+            let notice = format!("Ignored invalid message: {message}");
+            self.state.notice = Some((Instant::now(), notice));
+            self.state.parse_failures += 1;
+            return;
+        };
+        for (key, val) in record.values {
+            self.insert(&key, record.step, val);
+        }
+    }
+
+    /// Writes all series to `export_path` and reports the result as notice
+    fn export(&mut self) {
+        let notice = match export::export(&self.state, &self.export_path) {
+            Ok(()) => format!(
+                "Exported {} series to {}",
+                self.state.data.len(),
+                self.export_path.display()
+            ),
+            Err(err) => format!("Export failed: {err:#}"),
+        };
+        self.state.notice = Some((Instant::now(), notice));
+    }
+
+    /// Writes the current view to `snapshot_path` and reports the result as notice
+    fn snapshot(&mut self) {
+        let path = self.snapshot_path.clone();
+        let notice = match snapshot::write(self, &path, self.snapshot_size) {
+            Ok(()) => format!("Snapshot written to {}", path.display()),
+            Err(err) => format!("Snapshot failed: {err:#}"),
+        };
+        self.state.notice = Some((Instant::now(), notice));
+    }
+
     fn select_next(&mut self) {
         let num_keys = self.state.data.len();
         let Some(idx) = self.state.selection_list_state.selected_mut() else {
@@ -182,24 +503,233 @@ impl App {
         *idx -= 1;
     }
 
+    fn move_grid_selection(&mut self, code: KeyCode) {
+        let num_keys = self.state.data.len();
+        if num_keys == 0 {
+            return;
+        }
+
+        let idx = self.state.grid_selected;
+        let columns = self.state.grid_columns.max(1);
+        let page_size = self.state.grid_page_size.max(1);
+        let idx = match code {
+            KeyCode::Left => idx.saturating_sub(1),
+            KeyCode::Right => idx + 1,
+            KeyCode::Up => idx.saturating_sub(columns),
+            KeyCode::Down => idx + columns,
+            KeyCode::PageUp => idx.saturating_sub(page_size),
+            KeyCode::PageDown => idx + page_size,
+            _ => idx,
+        };
+        self.state.grid_selected = idx.min(num_keys - 1);
+    }
+
     fn enter_pressed(&mut self) {
         match self.state.ui_state {
             UiState::Plot => (),
             UiState::Passthrough => (),
+            UiState::Grid => {
+                let keys = self.state.sorted_keys();
+                let Some(key) = keys.get(self.state.grid_selected) else {
+                    return;
+                };
+                self.state.display_keys = vec![key.into()];
+                self.state.cursor = None;
+                self.state.ui_state = UiState::Plot;
+            }
             UiState::KeySelection => {
                 let Some(idx) = self.state.selection_list_state.selected() else {
                     return;
                 };
-                let mut keys: Vec<String> = self.state.data.keys().cloned().collect();
-                keys.sort();
-                let next_key = keys.get(idx).expect("Tried to access key out of bounds");
-                self.state.display_key = Some(next_key.into());
+                if self.state.marked_keys.is_empty() {
+                    let keys = self.state.sorted_keys();
+                    let next_key = keys.get(idx).expect("Tried to access key out of bounds");
+                    self.state.display_keys = vec![next_key.into()];
+                } else {
+                    self.state.display_keys = std::mem::take(&mut self.state.marked_keys)
+                        .into_iter()
+                        .collect();
+                }
+                self.state.cursor = None;
                 self.state.ui_state = UiState::Plot;
             }
         }
     }
 
+    /// Marks or unmarks the selected key for plotting several series at once
+    fn toggle_mark(&mut self) {
+        let Some(idx) = self.state.selection_list_state.selected() else {
+            return;
+        };
+        let Some(key) = self.state.sorted_keys().into_iter().nth(idx) else {
+            return;
+        };
+        if !self.state.marked_keys.remove(&key) {
+            self.state.marked_keys.insert(key);
+        }
+    }
+
+    /// Applies a zoom or pan operation to the step axis of the plot view
+    fn change_step_view(&mut self, change: impl Fn(&StepView, f64, f64) -> StepView) {
+        let Some((first, last)) = self.state.display_t_bounds() else {
+            return;
+        };
+        self.state.step_view = change(&self.state.step_view, first, last);
+    }
+
+    /// Shows the cursor on the last visible data point or hides it
+    /// Returns the indexes of the data points of the first displayed series in the
+    /// visible step range, which the cursor can be moved to
+    fn cursor_points(&self) -> Vec<usize> {
+        let (Some(key), Some((first, last))) = (
+            self.state.display_keys.first(),
+            self.state.display_t_bounds(),
+        ) else {
+            return Vec::new();
+        };
+        let Some(data) = self.state.data.get(key) else {
+            return Vec::new();
+        };
+        let (start, end) = self.state.step_view.range(first, last);
+        data.get_data()
+            .iter()
+            .enumerate()
+            .filter(|(_, (t, _))| (start..=end).contains(t))
+            .map(|(idx, _)| idx)
+            .collect()
+    }
+
+    fn toggle_cursor(&mut self) {
+        if self.state.cursor.take().is_some() || self.state.ui_state != UiState::Plot {
+            return;
+        }
+        self.state.cursor = self.cursor_points().last().copied();
+    }
+
+    /// Moves the cursor by `offset` data points, staying in the visible step range
+    fn move_cursor(&mut self, offset: isize) {
+        let Some(idx) = self.state.cursor else {
+            return;
+        };
+        let points = self.cursor_points();
+        let Some(max) = points.len().checked_sub(1) else {
+            return;
+        };
+        // The cursor may be outside of the range after zooming, it moves into it then
+        let pos = if offset > 0 {
+            points.partition_point(|point| *point <= idx) + offset.unsigned_abs() - 1
+        } else {
+            points
+                .partition_point(|point| *point < idx)
+                .saturating_sub(offset.unsigned_abs())
+        };
+        self.state.cursor = Some(points[pos.min(max)]);
+    }
+
+    /// Increases (`stronger == true`) or decreases the smoothing strength
+    ///
+    /// Steps the EMA factor by 0.05 or the window size by one value,
+    /// depending on the smoothing mode.
+    fn change_smoothing(&mut self, stronger: bool) {
+        let smoothing = &mut self.state.smoothing;
+        if smoothing.mode.uses_ema_factor() {
+            let delta = if stronger { -0.05 } else { 0.05 };
+            let ema_factor = ((smoothing.ema_factor + delta) * 100.0).round() / 100.0;
+            smoothing.ema_factor = ema_factor.clamp(0.0, 1.0);
+        } else if stronger {
+            smoothing.window += 1;
+        } else {
+            smoothing.window = usize::max(smoothing.window - 1, 1);
+        }
+    }
+
+    /// Toggles the y-axis scale of the current chart
+    fn toggle_log_scale(&mut self) {
+        let key = match self.state.ui_state {
+            UiState::Plot => self.state.display_keys.first().cloned(),
+            UiState::Grid => self
+                .state
+                .sorted_keys()
+                .into_iter()
+                .nth(self.state.grid_selected),
+            _ => None,
+        };
+        let Some(key) = key else {
+            return;
+        };
+        let log_scale = !self.state.is_log_scale(&key);
+        self.state.log_scale_overrides.insert(key, log_scale);
+    }
+
+    /// Edits the search or filter being typed in the passthrough view
+    fn edit_prompt(&mut self, code: KeyCode) {
+        let Some((kind, text)) = &mut self.state.log_view.prompt else {
+            return;
+        };
+        match code {
+            KeyCode::Char(c) => text.push(c),
+            KeyCode::Backspace => {
+                text.pop();
+            }
+            KeyCode::Enter => {
+                let (kind, text) = (*kind, std::mem::take(text));
+                self.state.log_view.prompt = None;
+                self.submit_prompt(kind, &text);
+            }
+            KeyCode::Esc => self.state.log_view.prompt = None,
+            _ => (),
+        }
+    }
+
+    /// Applies the entered search or filter, an empty pattern clears it
+    fn submit_prompt(&mut self, kind: PromptKind, pattern: &str) {
+        let regex = match prompt_regex(pattern) {
+            Ok(regex) => regex,
+            Err(err) => {
+                self.state.notice = Some((Instant::now(), format!("Invalid pattern: {err}")));
+                return;
+            }
+        };
+        let view = &mut self.state.log_view;
+        match kind {
+            PromptKind::Search => {
+                view.search = regex;
+                view.current_match = None;
+                if view.search.is_some() && !view.find(&self.state.linebuf, true) {
+                    let notice = format!("Pattern not found: {pattern}");
+                    self.state.notice = Some((Instant::now(), notice));
+                }
+            }
+            PromptKind::Filter => {
+                view.filter = regex;
+                view.scroll = None;
+            }
+        }
+    }
+
+    /// Jumps to the next older (`older == true`) or newer match of the search
+    fn find(&mut self, older: bool) {
+        let view = &mut self.state.log_view;
+        if view.search.is_none() || view.find(&self.state.linebuf, older) {
+            return;
+        }
+        let direction = if older { "older" } else { "newer" };
+        self.state.notice = Some((Instant::now(), format!("No {direction} match")));
+    }
+
+    /// Scrolls the passthrough view by `delta` lines, negative values scroll up
+    fn scroll_log(&mut self, delta: isize) {
+        self.state.log_view.scroll_by(&self.state.linebuf, delta);
+    }
+
     pub fn handle_keypress(&mut self, code: KeyCode) {
+        if self.state.log_view.prompt.is_some() {
+            self.edit_prompt(code);
+            return;
+        }
+
+        let passthrough = self.state.ui_state == UiState::Passthrough;
+        let page = self.state.log_view.height.max(1) as isize;
         match code {
             KeyCode::Char('q') | KeyCode::Char('Q') => {
                 self.quit();
@@ -210,6 +740,76 @@ impl App {
                     _ => UiState::Passthrough,
                 }
             }
+            KeyCode::Char('g') | KeyCode::Char('G') => {
+                self.state.ui_state = match self.state.ui_state {
+                    UiState::Grid => UiState::Plot,
+                    _ => UiState::Grid,
+                }
+            }
+            KeyCode::Char('l') | KeyCode::Char('L') => self.toggle_log_scale(),
+            KeyCode::Char('+') | KeyCode::Char('=') => {
+                self.change_step_view(|view, first, last| view.zoom(0.5, first, last))
+            }
+            KeyCode::Char('-') => {
+                self.change_step_view(|view, first, last| view.zoom(2.0, first, last))
+            }
+            KeyCode::Char(',') | KeyCode::Char('<') => {
+                self.change_step_view(|view, first, last| view.pan(-0.25, first, last))
+            }
+            KeyCode::Char('.') | KeyCode::Char('>') => {
+                self.change_step_view(|view, first, last| view.pan(0.25, first, last))
+            }
+            KeyCode::Char('t') | KeyCode::Char('T') => {
+                self.change_step_view(|view, first, last| view.tail(first, last))
+            }
+            KeyCode::Char('r') | KeyCode::Char('R') => self.state.step_view = StepView::Full,
+            KeyCode::Char('c') | KeyCode::Char('C') => self.toggle_cursor(),
+            KeyCode::Char('i') | KeyCode::Char('I') => {
+                self.state.stats_panel = !self.state.stats_panel
+            }
+            KeyCode::Char(']') => self.change_smoothing(true),
+            KeyCode::Char('[') => self.change_smoothing(false),
+            KeyCode::Char('m') | KeyCode::Char('M') => {
+                self.state.smoothing.mode = self.state.smoothing.mode.next()
+            }
+            KeyCode::Char('e') | KeyCode::Char('E') => self.export(),
+            KeyCode::Char('w') | KeyCode::Char('W') => self.snapshot(),
+            KeyCode::Char('/') if passthrough => {
+                self.state.log_view.prompt = Some((PromptKind::Search, String::new()))
+            }
+            KeyCode::Char('f') | KeyCode::Char('F') if passthrough => {
+                let filter = self.state.log_view.filter.as_ref();
+                let text = filter.map(|filter| filter.to_string()).unwrap_or_default();
+                self.state.log_view.prompt = Some((PromptKind::Filter, text))
+            }
+            KeyCode::Char('n') if passthrough => self.find(true),
+            KeyCode::Char('N') if passthrough => self.find(false),
+            KeyCode::Char('k') | KeyCode::Up if passthrough => self.scroll_log(-1),
+            KeyCode::Char('j') | KeyCode::Down if passthrough => self.scroll_log(1),
+            KeyCode::PageUp if passthrough => self.scroll_log(-page),
+            KeyCode::PageDown if passthrough => self.scroll_log(page),
+            KeyCode::Home if passthrough => self.scroll_log(isize::MIN),
+            KeyCode::End if passthrough => self.state.log_view.scroll = None,
+            KeyCode::Esc if passthrough => {
+                self.state.log_view.search = None;
+                self.state.log_view.current_match = None;
+            }
+            KeyCode::Esc => self.state.cursor = None,
+            KeyCode::Left | KeyCode::Right
+                if self.state.cursor.is_some() && self.state.ui_state == UiState::Plot =>
+            {
+                self.move_cursor(if code == KeyCode::Left { -1 } else { 1 })
+            }
+            KeyCode::Up
+            | KeyCode::Down
+            | KeyCode::Left
+            | KeyCode::Right
+            | KeyCode::PageUp
+            | KeyCode::PageDown
+                if self.state.ui_state == UiState::Grid =>
+            {
+                self.move_grid_selection(code)
+            }
             KeyCode::Up | KeyCode::Left => match self.state.ui_state {
                 UiState::KeySelection => self.select_previous(),
                 _ => self.state.ui_state = UiState::KeySelection,
@@ -219,6 +819,9 @@ impl App {
                 _ => self.state.ui_state = UiState::KeySelection,
             },
             KeyCode::Enter => self.enter_pressed(),
+            KeyCode::Char(' ') if self.state.ui_state == UiState::KeySelection => {
+                self.toggle_mark()
+            }
             _ => (),
         }
     }
@@ -227,6 +830,7 @@ impl App {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::smoothing::SmoothingMode;
 
     fn test_vec(app: &mut App, test_lines: Vec<(&str, &str, f64, f64)>) {
         for (line, key, t_exp, val_exp) in test_lines {
@@ -235,7 +839,7 @@ mod tests {
                 .state
                 .data
                 .get(key)
-                .expect(&format!("No entry for key {}!\nLine was {}", key, line))
+                .unwrap_or_else(|| panic!("No entry for key {}!\nLine was {}", key, line))
                 .data_points
                 .last()
                 .expect("No value added");
@@ -268,7 +872,53 @@ mod tests {
             ("loss 3.0", "loss", 1.0, 3.0),
         ];
 
-        test_vec(&mut app, test_lines)
+        test_vec(&mut app, test_lines);
+        assert_eq!(app.state.parse_failures, 0);
+
+        // Only JSON lines count as failures
+        app.process_line(r#"{"loss": 0."#);
+        app.process_line(r#"{"tag": "train"}"#);
+        app.process_line("{'loss': 0.5}");
+        assert_eq!(app.state.parse_failures, 3);
+    }
+
+    #[test]
+    fn explicit_steps() {
+        let mut app = App::new(5, 1.0);
+
+        let test_lines = vec![
+            ("Step 1000 loss 1.0", "loss", 1000.0, 1.0),
+            ("Step 2000 loss 0.5", "loss", 2000.0, 0.5),
+            ("Step 2000 val loss 0.7", "loss_val", 2000.0, 0.7),
+            ("loss 0.4", "loss", 2001.0, 0.4),
+        ];
+
+        test_vec(&mut app, test_lines);
+    }
+
+    #[test]
+    fn json_lines() {
+        let mut app = App::new(5, 1.0);
+
+        let test_lines = vec![
+            (r#"{"step": 10, "loss": 0.3}"#, "loss", 10.0, 0.3),
+            (
+                r#"{"step": 20, "loss": 0.2, "val/acc": 0.9}"#,
+                "val/acc",
+                20.0,
+                0.9,
+            ),
+            (
+                r#"{"global_step": 30, "train": {"loss": 0.1}}"#,
+                "train/loss",
+                30.0,
+                0.1,
+            ),
+            (r#"{"loss": 0.05}"#, "loss", 21.0, 0.05),
+        ];
+
+        test_vec(&mut app, test_lines);
+        assert!(!app.state.data.contains_key("step"));
     }
 
     #[test]
@@ -410,6 +1060,186 @@ mod tests {
         test_vec(&mut app, test_lines);
     }
 
+    #[test]
+    fn multi_select() {
+        let mut app = App::new(5, 1.0);
+
+        app.process_line("loss 1.0, acc 0.5");
+        app.process_line("val loss 1.5");
+        assert_eq!(app.state.display_keys, vec!["loss"]);
+
+        app.handle_keypress(KeyCode::Down);
+        app.toggle_mark();
+        app.select_next();
+        app.toggle_mark();
+        app.select_next();
+        app.toggle_mark();
+        app.select_previous();
+        app.toggle_mark();
+        app.enter_pressed();
+
+        assert!(app.state.ui_state == UiState::Plot);
+        assert_eq!(app.state.display_keys, vec!["acc", "loss_val"]);
+        assert!(app.state.marked_keys.is_empty());
+
+        // Without new marks the next selection shows the highlighted key only
+        app.handle_keypress(KeyCode::Down);
+        app.enter_pressed();
+        assert_eq!(app.state.display_keys, vec!["loss"]);
+    }
+
+    #[test]
+    fn grid_navigation() {
+        let mut app = App::new(5, 1.0);
+
+        app.process_line("aloss 1 bloss 2 closs 3 dloss 4 eloss 5");
+        app.handle_keypress(KeyCode::Char('g'));
+        app.state.grid_columns = 2;
+        app.state.grid_page_size = 4;
+
+        app.handle_keypress(KeyCode::Right);
+        app.handle_keypress(KeyCode::Down);
+        assert_eq!(app.state.grid_selected, 3);
+        app.handle_keypress(KeyCode::PageDown);
+        assert_eq!(app.state.grid_selected, 4);
+        app.handle_keypress(KeyCode::Up);
+        assert_eq!(app.state.grid_selected, 2);
+
+        app.handle_keypress(KeyCode::Enter);
+        assert!(app.state.ui_state == UiState::Plot);
+        assert_eq!(app.state.display_keys, vec!["closs"]);
+    }
+
+    #[test]
+    fn log_scale_toggle() {
+        let mut app = App::new(5, 1.0);
+        app.state.log_scale = true;
+
+        app.process_line("loss 1.0, acc 0.5");
+        assert!(app.state.is_log_scale("loss"));
+        app.handle_keypress(KeyCode::Char('l'));
+        assert!(!app.state.is_log_scale("loss"));
+        assert!(app.state.is_log_scale("acc"));
+    }
+
+    #[test]
+    fn zoom_and_pan() {
+        let mut app = App::new(5, 1.0);
+
+        for i in 0..=100 {
+            app.process_line(&format!("step {i} loss {}", 100 - i));
+        }
+
+        app.handle_keypress(KeyCode::Char('+'));
+        app.handle_keypress(KeyCode::Char('.'));
+        assert_eq!(
+            app.state.step_view,
+            StepView::Window {
+                start: 37.5,
+                end: 87.5
+            }
+        );
+        let (start, end) = app.state.step_view.range(0.0, 100.0);
+        let data = app.state.data.get("loss").unwrap();
+        assert_eq!(data.get_min_max_in(start, end), Some((13.0, 62.0)));
+
+        app.handle_keypress(KeyCode::Char('t'));
+        assert_eq!(app.state.step_view, StepView::Tail { width: 50.0 });
+
+        app.handle_keypress(KeyCode::Char('r'));
+        assert_eq!(app.state.step_view, StepView::Full);
+    }
+
+    #[test]
+    fn cursor() {
+        let mut app = App::new(5, 0.5);
+
+        app.process_line("step 0 loss 1.0");
+        app.process_line("step 10 loss 3.0");
+        app.process_line("step 20 loss 2.0");
+
+        app.handle_keypress(KeyCode::Char('c'));
+        assert_eq!(app.state.cursor, Some(2));
+        app.handle_keypress(KeyCode::Left);
+        app.handle_keypress(KeyCode::Left);
+        app.handle_keypress(KeyCode::Left);
+        assert_eq!(app.state.cursor, Some(0));
+        app.handle_keypress(KeyCode::Right);
+        assert!(app.state.ui_state == UiState::Plot);
+
+        let data = app.state.data.get("loss").unwrap();
+        assert_eq!(data.nearest(14.0), Some(1));
+
+        app.handle_keypress(KeyCode::Esc);
+        assert_eq!(app.state.cursor, None);
+
+        // The cursor stays on the points of the zoomed step range
+        app.process_line("step 30 loss 1.0");
+        app.state.step_view = StepView::Window {
+            start: 5.0,
+            end: 25.0,
+        };
+        app.handle_keypress(KeyCode::Char('c'));
+        assert_eq!(app.state.cursor, Some(2));
+        app.handle_keypress(KeyCode::Right);
+        assert_eq!(app.state.cursor, Some(2));
+        for _ in 0..3 {
+            app.handle_keypress(KeyCode::Left);
+        }
+        assert_eq!(app.state.cursor, Some(1));
+    }
+
+    #[test]
+    fn render_time_smoothing() {
+        let mut app = App::new(5, 0.5);
+
+        app.process_line("loss 1.0");
+        app.process_line("loss 3.0");
+        app.process_line("loss 2.0");
+
+        let data = app.state.data.get("loss").unwrap();
+        assert_eq!(data.get_data(), &vec![(0.0, 1.0), (1.0, 3.0), (2.0, 2.0)]);
+        assert_eq!(
+            data.smoothed(&app.state.smoothing),
+            vec![(0.0, 1.0), (1.0, 2.0), (2.0, 2.0)]
+        );
+        assert_eq!(data.get_min(), 1.0);
+        assert_eq!(data.get_max(), 3.0);
+
+        app.handle_keypress(KeyCode::Char(']'));
+        assert_eq!(app.state.smoothing.ema_factor, 0.45);
+        for _ in 0..20 {
+            app.handle_keypress(KeyCode::Char('['));
+        }
+        assert_eq!(app.state.smoothing.ema_factor, 1.0);
+
+        app.handle_keypress(KeyCode::Char('m'));
+        app.handle_keypress(KeyCode::Char('m'));
+        assert_eq!(app.state.smoothing.mode, SmoothingMode::TrailingMean);
+        app.handle_keypress(KeyCode::Char(']'));
+        assert_eq!(app.state.smoothing.window, 11);
+    }
+
+    #[test]
+    fn finished_input() {
+        let mut app = App::new(5, 1.0);
+
+        app.process_line("loss 2.0 acc 0.5");
+        app.process_line("loss 1.0");
+        app.finish_input(None);
+        let finished_at = app.state.finished_at;
+        assert!(finished_at.is_some());
+        app.finish_input(None);
+        assert_eq!(app.state.finished_at, finished_at);
+
+        assert_eq!(app.state.lines_read, 2);
+        assert_eq!(
+            app.summary(),
+            "acc: 1 points, last 0.5 at step 0, min 0.5, max 0.5\n\
+             loss: 2 points, last 1 at step 1, min 1, max 2\n"
+        );
+    }
+
     #[test]
     fn overflow() {
         let mut app = App::new(2, 1.0);
@@ -423,6 +1253,164 @@ mod tests {
             .linebuf
             .peek()
             .expect("There should be an element in the buffer");
-        assert_eq!(s, "cost 2.0");
+        assert_eq!(s, &(LineSource::Stdout, "cost 2.0".to_owned()));
+    }
+
+    #[test]
+    fn stderr_lines() {
+        let mut app = App::new(5, 1.0);
+
+        app.process_line("loss 1.0");
+        app.process_line_from(0, LineSource::Stderr, "aux_loss 2.0");
+
+        let sources: Vec<_> = app
+            .state
+            .linebuf
+            .iter()
+            .map(|(source, _)| *source)
+            .collect();
+        assert_eq!(sources, [LineSource::Stdout, LineSource::Stderr]);
+        assert_eq!(app.state.sorted_keys(), ["aux_loss", "loss"]);
+    }
+
+    #[test]
+    fn runs() {
+        let mut app = App::new(5, 1.0);
+        app.state.runs = vec!["a".into(), "b".into()];
+
+        app.process_line_from(0, LineSource::Stdout, "loss 1.0 acc 0.5");
+        app.process_line_from(1, LineSource::Stdout, r#"{"step": 3, "loss": 2.0}"#);
+        assert_eq!(app.state.sorted_keys(), ["acc@a", "loss@a", "loss@b"]);
+
+        app.state.display_keys = vec!["loss@b".into(), "acc@a".into()];
+        assert_eq!(app.state.plotted_keys(), ["loss@b", "loss@a", "acc@a"]);
+        assert_eq!(app.state.display_t_bounds(), Some((0.0, 3.0)));
+
+        // Metrics may contain `@` themselves
+        app.process_line_from(0, LineSource::Stdout, r#"{"step": 1, "top@5": 0.9}"#);
+        app.process_line_from(1, LineSource::Stdout, r#"{"step": 1, "top@5": 0.8}"#);
+        assert_eq!(app.state.split_key("top@5@b"), ("top@5", Some("b")));
+        assert_eq!(app.state.split_key("top@5"), ("top@5", None));
+        app.state.display_keys = vec!["top@5@a".into()];
+        assert_eq!(app.state.plotted_keys(), ["top@5@a", "top@5@b"]);
+
+        app.state.runs = vec!["a".into()];
+        assert_eq!(app.state.series_key(0, "loss"), "loss");
+        assert_eq!(app.state.split_key("top@5@a"), ("top@5@a", None));
+    }
+
+    #[test]
+    fn export_hotkey() {
+        let mut app = App::new(5, 1.0);
+        app.process_line("loss 1.0");
+        app.export_path =
+            std::env::temp_dir().join(format!("ezboard-{}-export.json", std::process::id()));
+
+        app.handle_keypress(KeyCode::Char('e'));
+        let (_, notice) = app.state.notice.clone().unwrap();
+        assert!(notice.starts_with("Exported 1 series"), "{notice}");
+        std::fs::remove_file(&app.export_path).unwrap();
+
+        app.export_path = PathBuf::from("export.txt");
+        app.handle_keypress(KeyCode::Char('e'));
+        let (_, notice) = app.state.notice.clone().unwrap();
+        assert!(notice.starts_with("Export failed"), "{notice}");
+    }
+
+    #[test]
+    fn snapshot_hotkey() {
+        let mut app = App::new(5, 1.0);
+        app.process_line("loss 1.0");
+        app.snapshot_path =
+            std::env::temp_dir().join(format!("ezboard-{}-snapshot.txt", std::process::id()));
+        app.snapshot_size = (40, 10);
+
+        app.handle_keypress(KeyCode::Char('w'));
+        let (_, notice) = app.state.notice.clone().unwrap();
+        assert!(notice.starts_with("Snapshot written"), "{notice}");
+        let content = std::fs::read_to_string(&app.snapshot_path).unwrap();
+        assert_eq!(content.lines().count(), 10);
+        std::fs::remove_file(&app.snapshot_path).unwrap();
+
+        app.snapshot_path = PathBuf::from("snapshot.csv");
+        app.handle_keypress(KeyCode::Char('w'));
+        let (_, notice) = app.state.notice.clone().unwrap();
+        assert!(notice.starts_with("Snapshot failed"), "{notice}");
+    }
+
+    #[test]
+    fn messages() {
+        let mut app = App::new(5, 1.0);
+        app.process_message(r#"{"key": "loss", "step": 10, "value": 0.5}"#);
+        app.process_message(r#"{"key": "loss", "value": 0.25}"#);
+        app.process_message(r#"{"step": 20, "acc": 0.5}"#);
+        app.process_message("loss 1.0");
+
+        assert_eq!(
+            app.state.data["loss"].get_data(),
+            &vec![(10.0, 0.5), (11.0, 0.25)]
+        );
+        assert_eq!(app.state.data["acc"].get_data(), &vec![(20.0, 0.5)]);
+        assert_eq!(app.state.display_keys, ["loss"]);
+        assert_eq!(app.state.linebuf.len(), 0);
+        let (_, notice) = app.state.notice.clone().unwrap();
+        assert_eq!(notice, "Ignored invalid message: loss 1.0");
+        app.process_message(r#"{"tag": "a"}"#);
+        assert_eq!(app.state.parse_failures, 2);
+    }
+
+    #[test]
+    fn passthrough_search() {
+        let mut app = App::new(20, 1.0);
+        for step in 0..20 {
+            app.process_line(&format!("Step {step} loss 1.0"));
+        }
+        app.state.log_view.height = 5;
+        app.handle_keypress(KeyCode::Char('p'));
+
+        for code in "/step 1q"
+            .chars()
+            .map(KeyCode::Char)
+            .chain([KeyCode::Enter])
+        {
+            app.handle_keypress(code);
+        }
+        assert!(app.running);
+        assert!(app.state.log_view.prompt.is_none());
+        let (_, notice) = app.state.notice.clone().unwrap();
+        assert_eq!(notice, "Pattern not found: step 1q");
+
+        for code in "/step 1\\d".chars().map(KeyCode::Char) {
+            app.handle_keypress(code);
+        }
+        app.handle_keypress(KeyCode::Backspace);
+        app.handle_keypress(KeyCode::Backspace);
+        app.handle_keypress(KeyCode::Char('7'));
+        app.handle_keypress(KeyCode::Enter);
+        assert_eq!(app.state.log_view.current_match, Some(2));
+        app.handle_keypress(KeyCode::Char('n'));
+        assert_eq!(app.state.log_view.current_match, Some(2));
+
+        for code in "fstep 1".chars().map(KeyCode::Char).chain([KeyCode::Enter]) {
+            app.handle_keypress(code);
+        }
+        assert_eq!(app.state.log_view.visible(&app.state.linebuf).len(), 11);
+        app.handle_keypress(KeyCode::Char('k'));
+        assert_eq!(app.state.log_view.window(&app.state.linebuf)[0], 14);
+        app.handle_keypress(KeyCode::End);
+        assert_eq!(app.state.log_view.scroll, None);
+    }
+
+    #[test]
+    fn timestamped_lines() {
+        let mut app = App::new(5, 1.0);
+
+        app.process_line(r#"2024-03-01T12:30:00.250Z {"step": 5, "loss": 1.0}"#);
+        app.process_line("2024-03-01T12:30:01.000Z Step 6 loss 2.0");
+
+        assert_eq!(
+            app.state.data["loss"].get_data(),
+            &vec![(5.0, 1.0), (6.0, 2.0)]
+        );
     }
 }
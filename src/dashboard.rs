@@ -0,0 +1,276 @@
+use std::sync::Arc;
+
+use serde_json::{json, Map, Value};
+
+use crate::{app::AppState, smoothing::Smoothing};
+
+/// Page of the web dashboard, it draws the updates of `/api/events`
+pub const PAGE: &str = include_str!("dashboard.html");
+
+/// Series of the app state as shown by the web dashboard
+///
+/// Smoothed values are computed with the smoothing of the TUI, so both views agree.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Snapshot {
+    pub smoothing: Smoothing,
+    /// Series sorted by key, unchanged ones are shared with the previous snapshot
+    pub series: Vec<Arc<Series>>,
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Series {
+    pub key: String,
+    pub steps: Vec<f64>,
+    pub values: Vec<f64>,
+    pub smoothed: Vec<f64>,
+}
+
+/// Points of the app state that are new since a snapshot
+///
+/// Taking the changes only copies the new points, so it's cheap enough for the UI task.
+/// The smoothing of the next snapshot is left to [`Changes::apply`], which can run on
+/// another thread.
+#[derive(Debug)]
+pub struct Changes {
+    smoothing: Smoothing,
+    /// Changes of all series, sorted by key
+    series: Vec<SeriesChange>,
+}
+
+#[derive(Debug)]
+struct SeriesChange {
+    key: String,
+    /// Number of points of the previous snapshot that are kept
+    kept: usize,
+    /// Points after the kept ones
+    new_points: Vec<(f64, f64)>,
+    /// Whether the series has exactly the points of the previous snapshot
+    unchanged: bool,
+}
+
+impl Changes {
+    /// Returns the changes of `state` since the snapshot `previous`
+    ///
+    /// Points are only ever appended to a series, so the ones of the previous snapshot
+    /// are kept.
+    pub fn new(state: &AppState, previous: &Snapshot) -> Self {
+        let series = state
+            .sorted_keys()
+            .into_iter()
+            .map(|key| {
+                let points = state.data[&key].get_data();
+                let old_len = previous.get(&key).map(|old| old.steps.len());
+                let kept = old_len.filter(|len| *len <= points.len()).unwrap_or(0);
+                SeriesChange {
+                    new_points: points[kept..].to_vec(),
+                    unchanged: old_len == Some(points.len()),
+                    key,
+                    kept,
+                }
+            })
+            .collect();
+        Self {
+            smoothing: state.smoothing,
+            series,
+        }
+    }
+
+    /// Returns whether the snapshot after the changes equals `previous`
+    pub fn is_empty(&self, previous: &Snapshot) -> bool {
+        self.smoothing == previous.smoothing
+            && self.series.len() == previous.series.len()
+            && self.series.iter().all(|change| change.unchanged)
+    }
+
+    /// Returns the snapshot after the changes, `previous` must be the snapshot they were
+    /// taken from
+    ///
+    /// Only the changed series are smoothed again.
+    pub fn apply(self, previous: &Snapshot) -> Snapshot {
+        let same_smoothing = self.smoothing == previous.smoothing;
+        let series = self
+            .series
+            .into_iter()
+            .map(|change| {
+                let old = previous.get(&change.key);
+                if let Some(old) = old.filter(|_| same_smoothing && change.unchanged) {
+                    return old.clone();
+                }
+                let (mut steps, mut values) = match old {
+                    Some(old) => (
+                        old.steps[..change.kept].to_vec(),
+                        old.values[..change.kept].to_vec(),
+                    ),
+                    None => Default::default(),
+                };
+                for (step, value) in change.new_points {
+                    steps.push(step);
+                    values.push(value);
+                }
+                let smoothed = self.smoothing.apply(&values);
+                Arc::new(Series {
+                    key: change.key,
+                    steps,
+                    values,
+                    smoothed,
+                })
+            })
+            .collect();
+        Snapshot {
+            smoothing: self.smoothing,
+            series,
+        }
+    }
+}
+
+impl Snapshot {
+    pub fn new(state: &AppState) -> Self {
+        let empty = Snapshot::default();
+        Changes::new(state, &empty).apply(&empty)
+    }
+
+    /// Returns the series `key`
+    fn get(&self, key: &str) -> Option<&Arc<Series>> {
+        let idx = self
+            .series
+            .binary_search_by(|series| series.key.as_str().cmp(key))
+            .ok()?;
+        self.series.get(idx)
+    }
+
+    /// Returns the update that turns `previous` into this snapshot
+    ///
+    /// Every changed series is sent from the first point that differs, the dashboard drops
+    /// its points from there on. Series shared with `previous` are skipped without comparing
+    /// their points. Without `previous`, or if a series is gone, the update resets the
+    /// dashboard and contains all points.
+    pub fn update(&self, previous: Option<&Snapshot>) -> Value {
+        let previous = previous.filter(|previous| {
+            previous
+                .series
+                .iter()
+                .all(|old| self.series.iter().any(|new| new.key == old.key))
+        });
+
+        let mut series = Map::new();
+        for new in &self.series {
+            let old = previous.and_then(|previous| previous.get(&new.key));
+            if old.is_some_and(|old| Arc::ptr_eq(old, new)) {
+                continue;
+            }
+            let start = old.map_or(0, |old| new.changed_from(old));
+            if start == new.steps.len() && old.is_some_and(|old| old.steps.len() == start) {
+                continue;
+            }
+            series.insert(
+                new.key.clone(),
+                json!({
+                    "start": start,
+                    "steps": new.steps[start..],
+                    "values": new.values[start..],
+                    "smoothed": new.smoothed[start..],
+                }),
+            );
+        }
+        json!({
+            "reset": previous.is_none(),
+            "smoothing": self.smoothing.label(),
+            "series": series,
+        })
+    }
+}
+
+impl Series {
+    /// Returns the index of the first point that differs from `old`
+    fn changed_from(&self, old: &Series) -> usize {
+        let same = |a: &[f64], b: &[f64]| {
+            a.iter()
+                .zip(b)
+                .take_while(|(a, b)| a.to_bits() == b.to_bits())
+                .count()
+        };
+        same(&self.steps, &old.steps)
+            .min(same(&self.values, &old.values))
+            .min(same(&self.smoothed, &old.smoothed))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::App;
+
+    #[test]
+    fn updates() {
+        let mut app = App::new(5, 0.5);
+        app.process_line("Step 1 loss 1.0");
+        app.process_line("Step 1 acc 0.5");
+        let first = Snapshot::new(&app.state);
+        assert_eq!(first.smoothing.label(), "EMA factor 0.50");
+
+        let reset = first.update(None);
+        assert_eq!(reset["reset"], true);
+        assert_eq!(reset["series"]["loss"]["steps"], json!([1.0]));
+        assert_eq!(reset["series"]["acc"]["smoothed"], json!([0.5]));
+        assert_eq!(first.update(Some(&first))["series"], json!({}));
+
+        app.process_line("Step 2 loss 3.0");
+        let second = Snapshot::new(&app.state);
+        let update = second.update(Some(&first));
+        assert_eq!(update["reset"], false);
+        assert_eq!(
+            update["series"],
+            json!({"loss": {"start": 1, "steps": [2.0], "values": [3.0], "smoothed": [2.0]}})
+        );
+
+        // Changing the smoothing resends the points whose smoothed value differs
+        app.state.smoothing.ema_factor = 1.0;
+        let third = Snapshot::new(&app.state);
+        let update = third.update(Some(&second));
+        assert_eq!(update["series"]["loss"]["start"], 1);
+        assert_eq!(update["series"]["loss"]["smoothed"], json!([3.0]));
+        assert_eq!(update["smoothing"], "EMA factor 1.00");
+
+        // A series that is gone resets the dashboard
+        app.state.data.remove("acc");
+        assert_eq!(
+            Snapshot::new(&app.state).update(Some(&third))["reset"],
+            true
+        );
+    }
+
+    #[test]
+    fn changes() {
+        let mut app = App::new(5, 0.5);
+        app.process_line("Step 1 loss 1.0");
+        app.process_line("Step 1 acc 0.5");
+        let first = Snapshot::new(&app.state);
+        assert!(Changes::new(&app.state, &first).is_empty(&first));
+
+        // Only the new points are taken, the result is the same as a new snapshot
+        app.process_line("Step 2 loss 3.0");
+        app.process_line("Step 1 aux_loss 0.1");
+        let changes = Changes::new(&app.state, &first);
+        assert!(!changes.is_empty(&first));
+        let new_points: Vec<_> = changes
+            .series
+            .iter()
+            .map(|change| (change.key.as_str(), change.new_points.len()))
+            .collect();
+        assert_eq!(new_points, [("acc", 0), ("aux_loss", 1), ("loss", 1)]);
+        let second = changes.apply(&first);
+        assert_eq!(second, Snapshot::new(&app.state));
+        assert!(Arc::ptr_eq(&first.series[0], &second.series[0]));
+        assert_eq!(second.update(Some(&first))["series"]["acc"], Value::Null);
+
+        app.state.smoothing.ema_factor = 1.0;
+        let changes = Changes::new(&app.state, &second);
+        assert!(!changes.is_empty(&second));
+        assert_eq!(changes.apply(&second), Snapshot::new(&app.state));
+
+        app.state.data.remove("acc");
+        let third = Snapshot::new(&app.state);
+        assert!(!Changes::new(&app.state, &second).is_empty(&second));
+        assert!(Changes::new(&app.state, &third).is_empty(&third));
+    }
+}
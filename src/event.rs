@@ -1,9 +1,14 @@
-use std::{path::PathBuf, pin::Pin, time::Duration};
+use std::{io::IsTerminal, path::PathBuf, process::ExitStatus, time::Duration};
 
-use crossterm::event::{Event as CrosstermEvent, KeyEvent};
-use futures::{FutureExt, StreamExt};
-use tokio::{fs::File, io::{stdin, AsyncBufRead, AsyncBufReadExt, BufReader, Lines}, sync::mpsc};
 use anyhow::Result;
+use crossterm::event::{Event as CrosstermEvent, KeyEvent};
+use futures::{future::select_all, FutureExt, StreamExt};
+use tokio::sync::mpsc;
+
+use crate::{
+    input::{ChildProcess, Input, LineSource, Tee},
+    listener::{ListenAddr, Listener},
+};
 
 /// Terminal events
 #[derive(Debug, Clone)]
@@ -12,10 +17,13 @@ pub enum Event {
     Tick,
     /// Key press
     Key(KeyEvent),
-    /// Input read and processed
-    LineRead(String),
-    ///
-    End,
+    /// Line read from the input with the given index, and where it was written
+    LineRead(usize, LineSource, String),
+    /// Metric message received by a listener
+    Message(String),
+    /// All input streams reached their end, sent only once. Carries the exit
+    /// status if the input is a child process
+    End(Option<ExitStatus>),
 }
 
 /// Combines terminal events, tick rate and io events in a single event stream.
@@ -25,21 +33,37 @@ pub struct EventStream {
     sender: mpsc::UnboundedSender<Event>,
     /// Event receiver channel
     receiver: mpsc::UnboundedReceiver<Event>,
-    /// Event handler thread
-    handler: tokio::task::JoinHandle<()>,
-    /// Input stream
-    input_stream: Lines<Pin<Box<dyn AsyncBufRead>>>
+    /// Event handler thread, if terminal events are read
+    handler: Option<tokio::task::JoinHandle<()>>,
+    /// Input streams, read concurrently
+    inputs: Vec<Input>,
+    /// Whether the only input is stdin
+    reads_stdin: bool,
+    /// Inputs that reached their end and aren't polled anymore
+    finished: Vec<bool>,
+    /// Whether all inputs reached their end
+    input_finished: bool,
+    /// Log file receiving a copy of the input
+    tee: Option<Tee>,
+    /// Sockets receiving metric messages
+    listeners: Vec<Listener>,
 }
 
 impl EventStream {
     /// Constructs a new instance of [`EventStream`].
-    pub async fn new(tick_rate: u64, input_file_path: &Option<PathBuf>) -> Self {
+    ///
+    /// Reads all `input_file_paths`, or stdin if there are none. With `follow`
+    /// the files are read continuously as they grow. A non-empty `command` is
+    /// run and its output is read instead.
+    pub async fn new(
+        tick_rate: u64,
+        input_file_paths: &[PathBuf],
+        follow: bool,
+        command: &[String],
+    ) -> Result<Self> {
+        let mut event_stream = Self::without_terminal(input_file_paths, follow, command).await?;
         let tick_rate = Duration::from_millis(tick_rate);
-        let (sender, receiver) = mpsc::unbounded_channel();
-        let _sender = sender.clone();
-
-        let input_file_path = input_file_path.clone();
-        let input_stream = get_input_stream(&input_file_path).await.unwrap().lines();
+        let _sender = event_stream.sender.clone();
 
         // Seperate thread for tick and key input events
         let handler = tokio::spawn(async move {
@@ -59,50 +83,175 @@ impl EventStream {
                         _sender.send(Event::Tick).unwrap();
                     }
                     Some(Ok(evt)) = crossterm_event => {
-                        match evt {
-                            CrosstermEvent::Key(key) => {
-                                if key.kind == crossterm::event::KeyEventKind::Press {
-                                    _sender.send(Event::Key(key)).unwrap();
-                                }
-                            },
-                            _ => (),
+                        if let CrosstermEvent::Key(key) = evt {
+                            if key.kind == crossterm::event::KeyEventKind::Press {
+                                _sender.send(Event::Key(key)).unwrap();
+                            }
                         }
                     },
                 };
             }
         });
-        Self {
+        event_stream.handler = Some(handler);
+        Ok(event_stream)
+    }
+
+    /// Constructs an [`EventStream`] that only emits input events, no ticks and keys
+    ///
+    /// Works without a terminal, the inputs are the same as for [`EventStream::new`].
+    pub async fn without_terminal(
+        input_file_paths: &[PathBuf],
+        follow: bool,
+        command: &[String],
+    ) -> Result<Self> {
+        let (sender, receiver) = mpsc::unbounded_channel();
+
+        let mut inputs = Vec::new();
+        let reads_stdin = command.is_empty() && input_file_paths.is_empty();
+        if !command.is_empty() {
+            inputs.push(Input::Child(ChildProcess::spawn(command)?));
+        } else if reads_stdin {
+            inputs.push(Input::open(None, follow).await?);
+        }
+        for path in input_file_paths {
+            inputs.push(Input::open(Some(path), follow).await?);
+        }
+
+        Ok(Self {
             sender,
             receiver,
-            handler,
-            input_stream,
+            handler: None,
+            finished: vec![false; inputs.len()],
+            inputs,
+            reads_stdin,
+            input_finished: false,
+            tee: None,
+            listeners: Vec::new(),
+        })
+    }
+
+    /// Writes every line read from the input to `tee`
+    pub fn with_tee(mut self, tee: Option<Tee>) -> Self {
+        self.tee = tee;
+        self
+    }
+
+    /// Receives metric messages on all `addrs`
+    ///
+    /// Stdin isn't read if it's a terminal, the metrics only come from the listeners then.
+    pub async fn with_listeners(mut self, addrs: &[ListenAddr]) -> Result<Self> {
+        for addr in addrs {
+            let listener = Listener::bind(addr, self.sender.clone()).await?;
+            self.listeners.push(listener);
+        }
+        if !addrs.is_empty() && self.reads_stdin && std::io::stdin().is_terminal() {
+            self = self.without_stdin();
+            // The metrics keep coming, so the input doesn't end
+            self.input_finished = true;
         }
+        Ok(self)
+    }
+
+    /// Doesn't read stdin, e.g. if all metrics were loaded from event files
+    ///
+    /// Without any other input, the input ends right away.
+    pub fn without_stdin(mut self) -> Self {
+        if self.reads_stdin {
+            self.inputs.clear();
+            self.finished.clear();
+            self.reads_stdin = false;
+        }
+        self
     }
 
     pub async fn next(&mut self) -> Event {
+        if self.inputs.is_empty() && !self.input_finished {
+            self.input_finished = true;
+            return Event::End(None);
+        }
         // file io is done in main thread
-        tokio::select! {
-            Some(evt) = self.receiver.recv() => {
-                evt
-            },
-            line = self.input_stream.next_line() => {
-                match line {
-                    Ok(Some(line)) => Event::LineRead(line),
-                    _ => Event::End
+        loop {
+            tokio::select! {
+                Some(evt) = self.receiver.recv() => {
+                    return evt;
+                },
+                (idx, line) = next_line(&mut self.inputs, &self.finished), if !self.input_finished => {
+                    match line {
+                        Ok(Some((source, line))) => {
+                            self.write_tee(&line).await;
+                            return Event::LineRead(idx, source, line);
+                        }
+                        _ => {
+                            if self.finish(idx) {
+                                return Event::End(self.exit_status());
+                            }
+                        }
+                    }
                 }
             }
         }
     }
-}
 
-async fn get_input_stream(path: &Option<PathBuf>) -> Result<Pin<Box<dyn AsyncBufRead>>> {
-    let reader: Pin<Box<dyn AsyncBufRead>> = match path {
-        Some(path) => {
-            let f = File::open(path).await?;
-            Box::pin(BufReader::new(f))
+    /// Process id of the command whose output is read, while it is running
+    pub fn child_id(&self) -> Option<u32> {
+        self.inputs.iter().find_map(Input::child_id)
+    }
+
+    /// Writes the remaining input to stdout, or stderr for lines the command wrote there,
+    /// until the input ends
+    ///
+    /// Returns the exit status if the input is a command.
+    pub async fn pass_through(&mut self) -> Result<Option<ExitStatus>> {
+        while !self.input_finished {
+            let (idx, line) = next_line(&mut self.inputs, &self.finished).await;
+            match line? {
+                Some((source, line)) => {
+                    self.write_tee(&line).await;
+                    match source {
+                        LineSource::Stdout => println!("{line}"),
+                        LineSource::Stderr => eprintln!("{line}"),
+                    }
+                }
+                None => {
+                    self.finish(idx);
+                }
+            }
         }
-        None => Box::pin(BufReader::new(stdin())),
-    };
+        Ok(self.exit_status())
+    }
+
+    fn exit_status(&self) -> Option<ExitStatus> {
+        self.inputs.iter().find_map(Input::exit_status)
+    }
+
+    /// Marks the input `idx` as finished, returns whether all inputs are finished now
+    fn finish(&mut self, idx: usize) -> bool {
+        self.finished[idx] = true;
+        self.input_finished = self.finished.iter().all(|finished| *finished);
+        self.input_finished
+    }
+
+    /// Copies `line` to the tee file, which is closed on the first write error
+    async fn write_tee(&mut self, line: &str) {
+        if let Some(tee) = &mut self.tee {
+            if tee.write_line(line).await.is_err() {
+                self.tee = None;
+            }
+        }
+    }
+}
 
-    Ok(reader)
+/// Returns the next line of any input that isn't finished yet, together with the index of the input
+///
+/// There has to be at least one unfinished input. Cancellation safe like [`Input::next_line`].
+async fn next_line(
+    inputs: &mut [Input],
+    finished: &[bool],
+) -> (usize, Result<Option<(LineSource, String)>>) {
+    let lines = inputs
+        .iter_mut()
+        .enumerate()
+        .filter(|(idx, _)| !finished[*idx])
+        .map(|(idx, input)| Box::pin(async move { (idx, input.next_line().await) }));
+    select_all(lines).await.0
 }
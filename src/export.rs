@@ -0,0 +1,154 @@
+use std::path::Path;
+
+use anyhow::{bail, Context, Result};
+use serde_json::{json, Map, Value};
+
+use crate::{app::AppState, utils::format_timestamp};
+
+/// File format of an export
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    /// One row per data point with the columns key, step, value, smoothed and timestamp
+    Csv,
+    /// Object with a list of data points per series
+    Json,
+}
+
+impl ExportFormat {
+    /// Chooses the format by the extension of `path`
+    pub fn from_path(path: &Path) -> Result<Self> {
+        let extension = path
+            .extension()
+            .map(|ext| ext.to_string_lossy().to_lowercase());
+        match extension.as_deref() {
+            Some("csv") => Ok(ExportFormat::Csv),
+            Some("json") => Ok(ExportFormat::Json),
+            _ => bail!(
+                "Can't export to {}, use a .csv or .json file",
+                path.display()
+            ),
+        }
+    }
+}
+
+/// Writes all series with raw and smoothed values to `path`, in the format given by its extension
+pub fn export(state: &AppState, path: &Path) -> Result<()> {
+    let content = match ExportFormat::from_path(path)? {
+        ExportFormat::Csv => to_csv(state),
+        ExportFormat::Json => serde_json::to_string_pretty(&to_json(state))? + "\n",
+    };
+    std::fs::write(path, content).with_context(|| format!("Failed to write {}", path.display()))
+}
+
+fn to_csv(state: &AppState) -> String {
+    let mut csv = String::from("key,step,value,smoothed,timestamp\n");
+    for key in state.sorted_keys() {
+        let data = &state.data[&key];
+        let smoothed = data.smoothed(&state.smoothing);
+        let rows = data
+            .get_data()
+            .iter()
+            .zip(smoothed)
+            .zip(data.get_timestamps());
+        for (((t, val), (_, smoothed)), time) in rows {
+            csv += &format!(
+                "{},{t},{val},{smoothed},{}\n",
+                csv_field(&key),
+                format_timestamp(*time)
+            );
+        }
+    }
+    csv
+}
+
+/// Quotes `field` if it contains a separator, quote or line break
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_owned()
+    }
+}
+
+fn to_json(state: &AppState) -> Value {
+    let mut series = Map::new();
+    for key in state.sorted_keys() {
+        let data = &state.data[&key];
+        let smoothed = data.smoothed(&state.smoothing);
+        let rows = data
+            .get_data()
+            .iter()
+            .zip(smoothed)
+            .zip(data.get_timestamps());
+        let points = rows
+            .map(|(((t, val), (_, smoothed)), time)| {
+                json!({
+                    "step": t,
+                    "value": val,
+                    "smoothed": smoothed,
+                    "timestamp": format_timestamp(*time),
+                })
+            })
+            .collect();
+        series.insert(key, Value::Array(points));
+    }
+    Value::Object(series)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::App;
+
+    fn app() -> App {
+        let mut app = App::new(5, 0.5);
+        app.process_line("Step 1 loss 1.0");
+        app.process_line("Step 2 loss 3.0");
+        app.process_line(r#"{"step": 2, "eval,acc": 0.5}"#);
+        app
+    }
+
+    #[test]
+    fn format_by_extension() {
+        assert_eq!(
+            ExportFormat::from_path(Path::new("out.CSV")).unwrap(),
+            ExportFormat::Csv
+        );
+        assert_eq!(
+            ExportFormat::from_path(Path::new("runs/out.json")).unwrap(),
+            ExportFormat::Json
+        );
+        assert!(ExportFormat::from_path(Path::new("out.txt")).is_err());
+        assert!(ExportFormat::from_path(Path::new("out")).is_err());
+    }
+
+    #[test]
+    fn csv() {
+        let csv = to_csv(&app().state);
+        let rows: Vec<_> = csv
+            .lines()
+            .map(|line| line.rsplit_once(',').unwrap().0)
+            .collect();
+        assert_eq!(
+            rows,
+            [
+                "key,step,value,smoothed",
+                "\"eval,acc\",2,0.5,0.5",
+                "loss,1,1,1",
+                "loss,2,3,2",
+            ]
+        );
+    }
+
+    #[test]
+    fn json() {
+        let json = to_json(&app().state);
+        let loss = &json["loss"];
+        assert_eq!(loss.as_array().unwrap().len(), 2);
+        assert_eq!(loss[1]["step"], 2.0);
+        assert_eq!(loss[1]["value"], 3.0);
+        assert_eq!(loss[1]["smoothed"], 2.0);
+        assert!(loss[1]["timestamp"].as_str().unwrap().ends_with('Z'));
+        assert_eq!(json["eval,acc"][0]["value"], 0.5);
+    }
+}
@@ -0,0 +1,634 @@
+use std::{
+    path::{Component, Path, PathBuf},
+    pin::Pin,
+    process::{ExitStatus, Stdio},
+    time::{Duration, SystemTime},
+};
+
+use anyhow::{bail, Context, Result};
+use tokio::{
+    fs::File,
+    io::{stdin, AsyncBufRead, AsyncBufReadExt, AsyncRead, AsyncWriteExt, BufReader, BufWriter},
+    process::{Child, Command},
+    sync::mpsc,
+};
+
+use crate::{tfevents::is_event_file, utils::format_timestamp};
+
+/// Interval in which a followed file is checked for new data
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Stream a line was written to
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LineSource {
+    /// Standard output of a child process, or any other input
+    #[default]
+    Stdout,
+    /// Standard error of a child process
+    Stderr,
+}
+
+/// Source of input lines
+pub enum Input {
+    /// Reads a file or stdin until its end
+    Stream(StreamReader),
+    /// Keeps reading a file as it grows, like `tail -F`
+    Follow(FollowFile),
+    /// Reads stdout and stderr of a command until it exits
+    Child(ChildProcess),
+}
+
+impl Input {
+    /// Opens the file at `path`, or stdin if there is no path
+    pub async fn open(path: Option<&Path>, follow: bool) -> Result<Self> {
+        let input = match path {
+            Some(path) if follow => Input::Follow(FollowFile::new(path)),
+            Some(path) => {
+                let f = File::open(path).await?;
+                Input::Stream(StreamReader::new(BufReader::new(f)))
+            }
+            None => Input::Stream(StreamReader::new(BufReader::new(stdin()))),
+        };
+
+        Ok(input)
+    }
+
+    /// Returns the next line, or `None` at the end of the input
+    ///
+    /// A followed file never ends, a child process ends when it has exited.
+    /// This method is cancellation safe.
+    pub async fn next_line(&mut self) -> Result<Option<(LineSource, String)>> {
+        match self {
+            Input::Stream(reader) => Ok(reader
+                .next_line()
+                .await?
+                .map(|line| (LineSource::Stdout, line))),
+            Input::Follow(file) => file
+                .next_line()
+                .await
+                .map(|line| Some((LineSource::Stdout, line))),
+            Input::Child(child) => child.next_line().await,
+        }
+    }
+
+    /// Exit status of the child process once it has exited
+    pub fn exit_status(&self) -> Option<ExitStatus> {
+        match self {
+            Input::Child(child) => child.status,
+            _ => None,
+        }
+    }
+
+    /// Process id of the running child process
+    pub fn child_id(&self) -> Option<u32> {
+        match self {
+            Input::Child(child) => child.child.id(),
+            _ => None,
+        }
+    }
+}
+
+/// Reads the lines of a file or stdin
+///
+/// Invalid UTF-8 is replaced like in the output of a command, instead of ending the input.
+pub struct StreamReader {
+    reader: Pin<Box<dyn AsyncBufRead>>,
+    /// Bytes of the current line read so far
+    buf: Vec<u8>,
+}
+
+impl StreamReader {
+    pub fn new(reader: impl AsyncBufRead + 'static) -> Self {
+        Self {
+            reader: Box::pin(reader),
+            buf: Vec::new(),
+        }
+    }
+
+    /// Returns the next line, or `None` at the end of the input
+    ///
+    /// This method is cancellation safe, a partially read line is kept in `buf`.
+    async fn next_line(&mut self) -> Result<Option<String>> {
+        let n = self.reader.read_until(b'\n', &mut self.buf).await?;
+        if n == 0 && self.buf.is_empty() {
+            return Ok(None);
+        }
+        let line = String::from_utf8_lossy(&self.buf);
+        let line = line.trim_end_matches(['\n', '\r']).to_owned();
+        self.buf.clear();
+        Ok(Some(line))
+    }
+}
+
+/// Expands the input arguments to a list of run names and file paths
+///
+/// An argument is a path, a glob like `runs/*/train.log` or either prefixed with
+/// `name=`. Runs without a name are labelled by the file stem, extended by parent
+/// directories until the labels are unique. TensorBoard event files are labelled
+/// by their directory.
+pub fn resolve_inputs(args: &[String]) -> Result<Vec<(String, PathBuf)>> {
+    let mut inputs: Vec<(Option<String>, PathBuf)> = Vec::new();
+    for arg in args {
+        let (name, pattern) = match arg.split_once('=') {
+            Some((name, pattern))
+                if !name.is_empty() && !name.contains(['/', '\\']) && !Path::new(arg).exists() =>
+            {
+                (Some(name), pattern)
+            }
+            _ => (None, arg.as_str()),
+        };
+
+        if !pattern.contains(['*', '?', '[']) {
+            inputs.push((name.map(String::from), PathBuf::from(pattern)));
+            continue;
+        }
+        let paths = glob::glob(pattern)?.collect::<Result<Vec<_>, _>>()?;
+        if paths.is_empty() {
+            bail!("No files match `{pattern}`");
+        }
+        let labels = run_labels(&paths);
+        let is_single = paths.len() == 1;
+        for (path, label) in paths.into_iter().zip(labels) {
+            let name = match name {
+                Some(name) if is_single => name.to_owned(),
+                Some(name) => format!("{name}/{label}"),
+                None => label,
+            };
+            inputs.push((Some(name), path));
+        }
+    }
+
+    let unnamed: Vec<PathBuf> = inputs
+        .iter()
+        .filter(|(name, _)| name.is_none())
+        .map(|(_, path)| path.clone())
+        .collect();
+    let mut labels = run_labels(&unnamed).into_iter();
+    let mut runs: Vec<(String, PathBuf)> = Vec::new();
+    for (name, path) in inputs {
+        let name = name.or_else(|| labels.next()).unwrap_or_default();
+        // `@` separates the run from the metric in series keys
+        let mut name = name.replace('@', "_");
+        if runs.iter().any(|(other, _)| *other == name) {
+            name = format!("{name}#{}", runs.len() + 1);
+        }
+        runs.push((name, path));
+    }
+    Ok(runs)
+}
+
+/// Labels files by their stem, prefixed by as many parent directories as needed
+/// to tell files with the same stem apart
+fn run_labels(paths: &[PathBuf]) -> Vec<String> {
+    let parts: Vec<Vec<String>> = paths
+        .iter()
+        .map(|path| {
+            let mut parts: Vec<String> = path
+                .parent()
+                .into_iter()
+                .flat_map(Path::components)
+                .filter_map(|c| match c {
+                    Component::Normal(part) => Some(part.to_string_lossy().into_owned()),
+                    _ => None,
+                })
+                .collect();
+            // Event files are named by their creation time, their directory names the run
+            if !is_event_file(path) || parts.is_empty() {
+                parts.push(
+                    path.file_stem()
+                        .unwrap_or_default()
+                        .to_string_lossy()
+                        .into_owned(),
+                );
+            }
+            parts
+        })
+        .collect();
+
+    let mut depths = vec![1; paths.len()];
+    loop {
+        let labels: Vec<String> = parts
+            .iter()
+            .zip(&depths)
+            .map(|(parts, depth)| parts[parts.len().saturating_sub(*depth)..].join("/"))
+            .collect();
+        let mut extended = false;
+        for (idx, label) in labels.iter().enumerate() {
+            let is_ambiguous = labels.iter().filter(|other| *other == label).count() > 1;
+            if is_ambiguous && depths[idx] < parts[idx].len() {
+                depths[idx] += 1;
+                extended = true;
+            }
+        }
+        if !extended {
+            return labels;
+        }
+    }
+}
+
+/// Log file receiving a copy of every input line
+pub struct Tee {
+    writer: BufWriter<File>,
+    /// Whether lines are prefixed with the time they were read
+    timestamps: bool,
+}
+
+impl Tee {
+    /// Creates the log file at `path`, replacing an existing file
+    pub async fn create(path: &Path, timestamps: bool) -> Result<Self> {
+        let file = File::create(path)
+            .await
+            .with_context(|| format!("Failed to create {}", path.display()))?;
+        Ok(Self {
+            writer: BufWriter::new(file),
+            timestamps,
+        })
+    }
+
+    /// Appends `line` and flushes it, so the file is complete even if ezboard is killed
+    pub async fn write_line(&mut self, line: &str) -> Result<()> {
+        if self.timestamps {
+            let timestamp = format_timestamp(SystemTime::now());
+            self.writer.write_all(timestamp.as_bytes()).await?;
+            self.writer.write_all(b" ").await?;
+        }
+        self.writer.write_all(line.as_bytes()).await?;
+        self.writer.write_all(b"\n").await?;
+        self.writer.flush().await?;
+        Ok(())
+    }
+}
+
+/// Command whose output is read line by line
+pub struct ChildProcess {
+    child: Child,
+    /// Lines of stdout and stderr in the order they were read
+    lines: mpsc::UnboundedReceiver<(LineSource, String)>,
+    status: Option<ExitStatus>,
+}
+
+impl ChildProcess {
+    /// Spawns `command`, the first element is the program and the rest its arguments
+    ///
+    /// Python output is unbuffered (`PYTHONUNBUFFERED`) unless the environment
+    /// says otherwise, so lines arrive as soon as they are printed.
+    pub fn spawn(command: &[String]) -> Result<Self> {
+        let (program, args) = command
+            .split_first()
+            .context("The command to run is empty")?;
+        let mut cmd = Command::new(program);
+        cmd.args(args)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped());
+        if std::env::var_os("PYTHONUNBUFFERED").is_none() {
+            cmd.env("PYTHONUNBUFFERED", "1");
+        }
+        let mut child = cmd
+            .spawn()
+            .with_context(|| format!("Failed to run `{program}`"))?;
+
+        let (sender, lines) = mpsc::unbounded_channel();
+        if let Some(stdout) = child.stdout.take() {
+            read_lines(stdout, LineSource::Stdout, sender.clone());
+        }
+        if let Some(stderr) = child.stderr.take() {
+            read_lines(stderr, LineSource::Stderr, sender);
+        }
+
+        Ok(Self {
+            child,
+            lines,
+            status: None,
+        })
+    }
+
+    async fn next_line(&mut self) -> Result<Option<(LineSource, String)>> {
+        if let Some(line) = self.lines.recv().await {
+            return Ok(Some(line));
+        }
+        // Both streams are closed, the process is about to exit
+        self.status = Some(self.child.wait().await?);
+        Ok(None)
+    }
+}
+
+/// Sends the lines of `stream` to `sender` until the stream is closed
+fn read_lines(
+    stream: impl AsyncRead + Unpin + Send + 'static,
+    source: LineSource,
+    sender: mpsc::UnboundedSender<(LineSource, String)>,
+) {
+    tokio::spawn(async move {
+        let mut reader = BufReader::new(stream);
+        let mut buf = Vec::new();
+        while let Ok(n) = reader.read_until(b'\n', &mut buf).await {
+            if n == 0 {
+                break;
+            }
+            let line = String::from_utf8_lossy(&buf);
+            let line = line.trim_end_matches(['\n', '\r']).to_owned();
+            if sender.send((source, line)).is_err() {
+                break;
+            }
+            buf.clear();
+        }
+    });
+}
+
+/// Exit code of ezboard for a child process with `status`
+///
+/// Like shells, a process killed by a signal results in 128 + the signal number.
+pub fn exit_code(status: ExitStatus) -> u8 {
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::ExitStatusExt;
+        if let Some(signal) = status.signal() {
+            return (128 + signal) as u8;
+        }
+    }
+    status.code().unwrap_or(1) as u8
+}
+
+/// Sends an interrupt signal (like Ctrl-C) to the process with id `pid`
+#[cfg(unix)]
+pub fn interrupt(pid: u32) {
+    send_signal(pid, libc::SIGINT);
+}
+
+#[cfg(not(unix))]
+pub fn interrupt(_pid: u32) {}
+
+#[cfg(unix)]
+fn send_signal(pid: u32, signal: i32) {
+    // SAFETY: kill has no memory safety requirements
+    unsafe {
+        libc::kill(pid as libc::pid_t, signal);
+    }
+}
+
+/// Forwards interrupt, termination and hangup signals received by ezboard to `pid`
+///
+/// ezboard itself keeps running to show the end of the run.
+#[cfg(unix)]
+pub fn forward_signals(pid: u32) -> Result<()> {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    for kind in [
+        SignalKind::interrupt(),
+        SignalKind::terminate(),
+        SignalKind::hangup(),
+    ] {
+        let mut signals = signal(kind)?;
+        tokio::spawn(async move {
+            while signals.recv().await.is_some() {
+                send_signal(pid, kind.as_raw_value());
+            }
+        });
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn forward_signals(_pid: u32) -> Result<()> {
+    Ok(())
+}
+
+/// File that is read continuously while it is being written
+///
+/// The file is reopened if it is truncated or replaced (e.g. by log rotation),
+/// and it is waited for if it doesn't exist yet.
+pub struct FollowFile {
+    path: PathBuf,
+    reader: Option<BufReader<File>>,
+    /// Identity of the opened file
+    id: Option<FileId>,
+    /// Bytes of the current line read so far
+    buf: Vec<u8>,
+    /// Bytes of complete lines read from the opened file
+    pos: u64,
+}
+
+impl FollowFile {
+    pub fn new(path: &Path) -> Self {
+        Self {
+            path: path.to_owned(),
+            reader: None,
+            id: None,
+            buf: Vec::new(),
+            pos: 0,
+        }
+    }
+
+    async fn next_line(&mut self) -> Result<String> {
+        loop {
+            if self.reader.is_none() {
+                self.open().await;
+            }
+
+            if let Some(reader) = &mut self.reader {
+                let n = reader.read_until(b'\n', &mut self.buf).await?;
+                if self.buf.ends_with(b"\n") {
+                    self.pos += self.buf.len() as u64;
+                    let line = String::from_utf8_lossy(&self.buf);
+                    let line = line.trim_end_matches(['\n', '\r']).to_owned();
+                    self.buf.clear();
+                    return Ok(line);
+                }
+                if n > 0 {
+                    continue;
+                }
+                if self.was_replaced().await {
+                    self.reader = None;
+                    continue;
+                }
+            }
+
+            tokio::time::sleep(POLL_INTERVAL).await;
+        }
+    }
+
+    async fn open(&mut self) {
+        let Ok(f) = File::open(&self.path).await else {
+            return;
+        };
+        self.id = f.metadata().await.ok().map(|m| file_id(&m));
+        self.reader = Some(BufReader::new(f));
+        self.buf.clear();
+        self.pos = 0;
+    }
+
+    /// Checks whether the file at `path` was truncated or is a different file now
+    async fn was_replaced(&self) -> bool {
+        let Ok(metadata) = tokio::fs::metadata(&self.path).await else {
+            // Rotated away and not recreated yet, keep the old file until it is
+            return false;
+        };
+        metadata.len() < self.pos || Some(file_id(&metadata)) != self.id
+    }
+}
+
+#[cfg(unix)]
+type FileId = (u64, u64);
+
+#[cfg(unix)]
+fn file_id(metadata: &std::fs::Metadata) -> FileId {
+    use std::os::unix::fs::MetadataExt;
+    (metadata.dev(), metadata.ino())
+}
+
+#[cfg(not(unix))]
+type FileId = ();
+
+#[cfg(not(unix))]
+fn file_id(_metadata: &std::fs::Metadata) -> FileId {}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use super::*;
+    use crate::utils::strip_timestamp;
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("ezboard-{}-{name}", std::process::id()))
+    }
+
+    async fn next(file: &mut FollowFile) -> String {
+        tokio::time::timeout(Duration::from_secs(5), file.next_line())
+            .await
+            .expect("Timed out waiting for a line")
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn follow_appended_lines() {
+        let path = temp_path("append.log");
+        std::fs::write(&path, "loss 1\nloss").unwrap();
+
+        let mut file = FollowFile::new(&path);
+        assert_eq!(next(&mut file).await, "loss 1");
+
+        let mut f = std::fs::OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .unwrap();
+        write!(f, " 2\r\nloss 3\n").unwrap();
+        assert_eq!(next(&mut file).await, "loss 2");
+        assert_eq!(next(&mut file).await, "loss 3");
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn invalid_utf8() {
+        // Like stdin with a line of another encoding
+        let data: &[u8] = b"loss 1\nl\xf6ss 2\r\nloss 3";
+        let mut input = Input::Stream(StreamReader::new(data));
+        let mut lines = Vec::new();
+        while let Some((_, line)) = input.next_line().await.unwrap() {
+            lines.push(line);
+        }
+        assert_eq!(lines, ["loss 1", "l\u{fffd}ss 2", "loss 3"]);
+    }
+
+    #[tokio::test]
+    async fn child_output() {
+        let command = ["sh", "-c", "echo loss 1; echo warning >&2; exit 3"].map(String::from);
+        let mut input = Input::Child(ChildProcess::spawn(&command).unwrap());
+
+        let mut lines = Vec::new();
+        while let Some(line) = input.next_line().await.unwrap() {
+            lines.push(line);
+        }
+        lines.sort_by_key(|(source, _)| *source == LineSource::Stderr);
+        assert_eq!(
+            lines,
+            [
+                (LineSource::Stdout, "loss 1".to_owned()),
+                (LineSource::Stderr, "warning".to_owned())
+            ]
+        );
+        assert_eq!(input.exit_status().map(exit_code), Some(3));
+        assert!(ChildProcess::spawn(&[]).is_err());
+    }
+
+    #[test]
+    fn labels() {
+        let paths = ["runs/a/train.log", "runs/b/train.log", "other/eval.log"].map(PathBuf::from);
+        assert_eq!(run_labels(&paths), ["a/train", "b/train", "eval"]);
+
+        let args = ["base=runs/a/train.log", "runs/b/train.log", "c/train.log"].map(String::from);
+        let runs = resolve_inputs(&args).unwrap();
+        let names: Vec<_> = runs.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(names, ["base", "b/train", "c/train"]);
+        assert_eq!(runs[0].1, PathBuf::from("runs/a/train.log"));
+
+        let paths = [
+            "runs/a/events.out.tfevents.1.host",
+            "runs/b/events.out.tfevents.2.host",
+        ];
+        assert_eq!(run_labels(&paths.map(PathBuf::from)), ["a", "b"]);
+        let paths = [PathBuf::from("events.out.tfevents.1.host")];
+        assert_eq!(run_labels(&paths), ["events.out.tfevents.1"]);
+    }
+
+    #[test]
+    fn globs() {
+        let dir = temp_path("runs");
+        for run in ["a", "b"] {
+            std::fs::create_dir_all(dir.join(run)).unwrap();
+            std::fs::write(dir.join(run).join("train.log"), "loss 1\n").unwrap();
+        }
+
+        let pattern = format!("{}/*/train.log", dir.display());
+        let runs = resolve_inputs(std::slice::from_ref(&pattern)).unwrap();
+        let names: Vec<_> = runs.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(names, ["a/train", "b/train"]);
+
+        let runs = resolve_inputs(&[format!("exp={pattern}")]).unwrap();
+        assert_eq!(runs[1].0, "exp/b/train");
+        assert!(resolve_inputs(&[format!("{}/*/eval.log", dir.display())]).is_err());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn tee() {
+        let path = temp_path("tee.log");
+
+        let mut tee = Tee::create(&path, false).await.unwrap();
+        tee.write_line("loss 1").await.unwrap();
+        tee.write_line("loss 2").await.unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "loss 1\nloss 2\n");
+
+        let mut tee = Tee::create(&path, true).await.unwrap();
+        tee.write_line("loss 3").await.unwrap();
+        let content = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(strip_timestamp(&content), "loss 3\n");
+        assert_ne!(content, "loss 3\n");
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn follow_truncation_and_rotation() {
+        let path = temp_path("rotate.log");
+        std::fs::write(&path, "loss 1\nloss 2\n").unwrap();
+
+        let mut file = FollowFile::new(&path);
+        assert_eq!(next(&mut file).await, "loss 1");
+        assert_eq!(next(&mut file).await, "loss 2");
+
+        std::fs::write(&path, "loss 3\n").unwrap();
+        assert_eq!(next(&mut file).await, "loss 3");
+
+        let rotated = temp_path("rotate.log.1");
+        std::fs::rename(&path, &rotated).unwrap();
+        std::fs::write(&path, "loss 4\n").unwrap();
+        assert_eq!(next(&mut file).await, "loss 4");
+
+        std::fs::remove_file(&path).unwrap();
+        std::fs::remove_file(&rotated).unwrap();
+    }
+}
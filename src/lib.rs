@@ -1,5 +1,20 @@
 pub mod app;
+pub mod dashboard;
 pub mod event;
+pub mod export;
+pub mod input;
+pub mod listener;
+pub mod log_view;
+pub mod parser;
+pub mod prometheus;
+pub mod scale;
+pub mod server;
+pub mod session;
+pub mod smoothing;
+pub mod snapshot;
+pub mod stats;
+pub mod tfevents;
 pub mod tui;
 pub mod ui;
 pub mod utils;
+pub mod view;
@@ -0,0 +1,252 @@
+use std::{
+    net::{Ipv4Addr, SocketAddr},
+    path::PathBuf,
+    str::FromStr,
+};
+
+use anyhow::{bail, Context, Result};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncRead, BufReader},
+    net::{TcpListener, UdpSocket},
+    sync::mpsc,
+    task::JoinHandle,
+};
+
+use crate::event::Event;
+
+/// Largest UDP datagram that is received
+const MAX_DATAGRAM_SIZE: usize = 65_536;
+
+/// Address metric messages are received on
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ListenAddr {
+    /// Unix domain socket at this path, every connection sends JSON lines
+    Unix(PathBuf),
+    /// TCP port, every connection sends JSON lines
+    Tcp(SocketAddr),
+    /// UDP port, every datagram contains one or more JSON lines
+    Udp(SocketAddr),
+}
+
+impl FromStr for ListenAddr {
+    type Err = String;
+
+    /// Parses `unix:PATH`, `tcp:PORT` or `udp:PORT`. Ports are opened on localhost
+    /// unless they are given with a host, e.g. `tcp:0.0.0.0:9000`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once(':') {
+            Some(("unix", path)) if !path.is_empty() => Ok(ListenAddr::Unix(path.into())),
+            Some(("tcp", addr)) => parse_socket_addr(addr).map(ListenAddr::Tcp),
+            Some(("udp", addr)) => parse_socket_addr(addr).map(ListenAddr::Udp),
+            _ => Err(format!(
+                "`{s}` isn't an address like unix:/tmp/ezboard.sock, tcp:9000 or udp:9000"
+            )),
+        }
+    }
+}
+
+/// Parses a port, which is opened on localhost, or an address like `0.0.0.0:9000`
+pub fn parse_socket_addr(addr: &str) -> Result<SocketAddr, String> {
+    if let Ok(port) = addr.parse::<u16>() {
+        return Ok(SocketAddr::from((Ipv4Addr::LOCALHOST, port)));
+    }
+    addr.parse()
+        .map_err(|_| format!("`{addr}` isn't a port or an address like 127.0.0.1:9000"))
+}
+
+/// Receives metric messages and sends them as [`Event::Message`]
+///
+/// A unix socket file is removed when the listener is dropped.
+pub struct Listener {
+    /// Task accepting connections or receiving datagrams
+    task: JoinHandle<()>,
+    /// Path of the unix socket
+    socket_path: Option<PathBuf>,
+}
+
+impl Listener {
+    /// Binds to `addr` and sends every received line to `sender`
+    pub async fn bind(addr: &ListenAddr, sender: mpsc::UnboundedSender<Event>) -> Result<Self> {
+        let (task, socket_path) = match addr {
+            ListenAddr::Unix(path) => (Self::bind_unix(path, sender)?, Some(path.clone())),
+            ListenAddr::Tcp(addr) => {
+                let listener = TcpListener::bind(addr)
+                    .await
+                    .with_context(|| format!("Failed to listen on tcp:{addr}"))?;
+                let task = tokio::spawn(async move {
+                    while let Ok((stream, _)) = listener.accept().await {
+                        tokio::spawn(read_messages(stream, sender.clone()));
+                    }
+                });
+                (task, None)
+            }
+            ListenAddr::Udp(addr) => {
+                let socket = UdpSocket::bind(addr)
+                    .await
+                    .with_context(|| format!("Failed to listen on udp:{addr}"))?;
+                let task = tokio::spawn(async move {
+                    let mut buf = vec![0; MAX_DATAGRAM_SIZE];
+                    while let Ok(len) = socket.recv(&mut buf).await {
+                        let datagram = String::from_utf8_lossy(&buf[..len]);
+                        for line in datagram.lines().filter(|line| !line.trim().is_empty()) {
+                            if sender.send(Event::Message(line.to_owned())).is_err() {
+                                return;
+                            }
+                        }
+                    }
+                });
+                (task, None)
+            }
+        };
+        Ok(Self { task, socket_path })
+    }
+
+    #[cfg(unix)]
+    fn bind_unix(
+        path: &std::path::Path,
+        sender: mpsc::UnboundedSender<Event>,
+    ) -> Result<JoinHandle<()>> {
+        use std::os::unix::{fs::FileTypeExt, net::UnixStream};
+        use tokio::net::UnixListener;
+
+        // A socket left behind by a crashed instance doesn't accept connections anymore
+        if UnixStream::connect(path).is_ok() {
+            bail!("{} is already used by another process", path.display());
+        }
+        let metadata = std::fs::symlink_metadata(path);
+        if metadata.is_ok_and(|metadata| metadata.file_type().is_socket()) {
+            let _ = std::fs::remove_file(path);
+        }
+        let listener = UnixListener::bind(path)
+            .with_context(|| format!("Failed to listen on unix:{}", path.display()))?;
+        Ok(tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                tokio::spawn(read_messages(stream, sender.clone()));
+            }
+        }))
+    }
+
+    #[cfg(not(unix))]
+    fn bind_unix(
+        path: &std::path::Path,
+        _sender: mpsc::UnboundedSender<Event>,
+    ) -> Result<JoinHandle<()>> {
+        bail!(
+            "Can't listen on unix:{}, unix sockets aren't supported on this platform",
+            path.display()
+        )
+    }
+}
+
+impl Drop for Listener {
+    fn drop(&mut self) {
+        self.task.abort();
+        if let Some(path) = &self.socket_path {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+}
+
+/// Sends every non-empty line of `stream` until it's closed
+async fn read_messages(stream: impl AsyncRead + Unpin, sender: mpsc::UnboundedSender<Event>) {
+    let mut lines = BufReader::new(stream).lines();
+    while let Ok(Some(line)) = lines.next_line().await {
+        if line.trim().is_empty() {
+            continue;
+        }
+        if sender.send(Event::Message(line)).is_err() {
+            return;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::io::AsyncWriteExt;
+
+    use super::*;
+
+    #[test]
+    fn parse_addr() {
+        assert_eq!(
+            "unix:/tmp/ezboard.sock".parse(),
+            Ok(ListenAddr::Unix("/tmp/ezboard.sock".into()))
+        );
+        assert_eq!(
+            "tcp:9000".parse(),
+            Ok(ListenAddr::Tcp("127.0.0.1:9000".parse().unwrap()))
+        );
+        assert_eq!(
+            "udp:0.0.0.0:9000".parse(),
+            Ok(ListenAddr::Udp("0.0.0.0:9000".parse().unwrap()))
+        );
+        assert!("9000".parse::<ListenAddr>().is_err());
+        assert!("unix:".parse::<ListenAddr>().is_err());
+        assert!("tcp:localhost".parse::<ListenAddr>().is_err());
+    }
+
+    async fn next_message(receiver: &mut mpsc::UnboundedReceiver<Event>) -> String {
+        match receiver.recv().await {
+            Some(Event::Message(message)) => message,
+            event => panic!("Expected a message, got {event:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn tcp() {
+        let (sender, mut receiver) = mpsc::unbounded_channel();
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        drop(listener);
+        let _listener = Listener::bind(&ListenAddr::Tcp(addr), sender)
+            .await
+            .unwrap();
+
+        let mut first = tokio::net::TcpStream::connect(addr).await.unwrap();
+        let mut second = tokio::net::TcpStream::connect(addr).await.unwrap();
+        first.write_all(b"{\"key\": \"loss\"").await.unwrap();
+        second.write_all(b"{\"key\": \"acc\"}\n\n").await.unwrap();
+        assert_eq!(next_message(&mut receiver).await, r#"{"key": "acc"}"#);
+        first.write_all(b", \"value\": 1}\n").await.unwrap();
+        assert_eq!(
+            next_message(&mut receiver).await,
+            r#"{"key": "loss", "value": 1}"#
+        );
+    }
+
+    #[tokio::test]
+    async fn udp() {
+        let (sender, mut receiver) = mpsc::unbounded_channel();
+        let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let addr = socket.local_addr().unwrap();
+        drop(socket);
+        let _listener = Listener::bind(&ListenAddr::Udp(addr), sender)
+            .await
+            .unwrap();
+
+        let client = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        client
+            .send_to(b"{\"a\": 1}\n{\"b\": 2}", addr)
+            .await
+            .unwrap();
+        assert_eq!(next_message(&mut receiver).await, r#"{"a": 1}"#);
+        assert_eq!(next_message(&mut receiver).await, r#"{"b": 2}"#);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn unix_socket() {
+        let path = std::env::temp_dir().join(format!("ezboard-{}.sock", std::process::id()));
+        let addr = ListenAddr::Unix(path.clone());
+        let (sender, mut receiver) = mpsc::unbounded_channel();
+        let listener = Listener::bind(&addr, sender.clone()).await.unwrap();
+        assert!(Listener::bind(&addr, sender).await.is_err());
+
+        let mut stream = tokio::net::UnixStream::connect(&path).await.unwrap();
+        stream.write_all(b"{\"a\": 1}\n").await.unwrap();
+        assert_eq!(next_message(&mut receiver).await, r#"{"a": 1}"#);
+
+        drop(listener);
+        assert!(!path.exists());
+    }
+}
@@ -0,0 +1,265 @@
+use regex::{Regex, RegexBuilder};
+
+use crate::{input::LineSource, utils::RingBuffer};
+
+type Lines = RingBuffer<(LineSource, String)>;
+
+/// What the text typed into the passthrough view is used for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PromptKind {
+    /// Highlight and jump to matching lines
+    Search,
+    /// Only show matching lines
+    Filter,
+}
+
+/// Scroll position, search and filter of the passthrough view
+///
+/// Lines are referred to by their distance from the newest line, so the view
+/// stays on the same lines while new ones arrive.
+#[derive(Debug, Default)]
+pub struct LogView {
+    /// Number of lines after the last displayed one, `None` follows new lines
+    pub scroll: Option<usize>,
+    /// Number of lines shown by the last rendered view
+    pub height: usize,
+    /// Highlighted pattern, `n` and `N` jump between its matches
+    pub search: Option<Regex>,
+    /// Line of the current search match, counted from the newest line
+    pub current_match: Option<usize>,
+    /// Only lines matching this pattern are shown
+    pub filter: Option<Regex>,
+    /// Search or filter being typed
+    pub prompt: Option<(PromptKind, String)>,
+}
+
+/// Compiles the pattern typed into a prompt, or `None` if it's empty
+///
+/// Patterns without upper case letters match case insensitively.
+pub fn prompt_regex(pattern: &str) -> Result<Option<Regex>, regex::Error> {
+    if pattern.is_empty() {
+        return Ok(None);
+    }
+    let case_insensitive = !pattern.chars().any(char::is_uppercase);
+    RegexBuilder::new(pattern)
+        .case_insensitive(case_insensitive)
+        .build()
+        .map(Some)
+}
+
+impl LogView {
+    /// Keeps the view and the current match on their lines when a line is added
+    pub fn line_added(&mut self) {
+        if let Some(scroll) = &mut self.scroll {
+            *scroll += 1;
+        }
+        if let Some(current_match) = &mut self.current_match {
+            *current_match += 1;
+        }
+    }
+
+    /// Returns the indexes of the lines passing the filter
+    pub fn visible(&self, lines: &Lines) -> Vec<usize> {
+        lines
+            .iter()
+            .enumerate()
+            .filter(|(_, (_, line))| self.filter.as_ref().is_none_or(|f| f.is_match(line)))
+            .map(|(idx, _)| idx)
+            .collect()
+    }
+
+    /// Returns the indexes of the displayed lines, oldest first
+    pub fn window(&self, lines: &Lines) -> Vec<usize> {
+        let visible = self.visible(lines);
+        let end = self.end(&visible, lines.len());
+        visible[end.saturating_sub(self.height.max(1))..end].to_vec()
+    }
+
+    /// Returns the index of the current match in `lines`
+    pub fn current_match(&self, lines: &Lines) -> Option<usize> {
+        let current_match = self.current_match?;
+        lines.len().checked_sub(current_match + 1)
+    }
+
+    /// Scrolls by `delta` lines, negative values scroll towards older lines
+    ///
+    /// Reaching the newest line follows new lines again.
+    pub fn scroll_by(&mut self, lines: &Lines, delta: isize) {
+        let visible = self.visible(lines);
+        let end = self
+            .end(&visible, lines.len())
+            .saturating_add_signed(delta)
+            .clamp(self.min_end(&visible), visible.len());
+        if end == visible.len() {
+            self.scroll = None;
+        } else {
+            self.set_end(&visible, lines.len(), end);
+        }
+    }
+
+    /// Jumps to the next match of the search towards older (`older == true`) or newer lines
+    ///
+    /// Without a current match, the search starts at the last or the first displayed line.
+    /// Returns whether a match was found.
+    pub fn find(&mut self, lines: &Lines, older: bool) -> bool {
+        let Some(search) = &self.search else {
+            return false;
+        };
+        let visible = self.visible(lines);
+        let end = self.end(&visible, lines.len());
+        let current = self.current_match(lines);
+        let is_match = |idx: &usize| {
+            let (_, line) = lines.get(*idx).expect("visible lines are in the buffer");
+            search.is_match(line)
+        };
+
+        let found = if older {
+            let before = match current {
+                Some(current) => visible.partition_point(|idx| *idx < current),
+                None => end,
+            };
+            visible[..before].iter().rposition(is_match)
+        } else {
+            let after = match current {
+                Some(current) => visible.partition_point(|idx| *idx <= current),
+                None => end.saturating_sub(self.height),
+            };
+            visible[after..]
+                .iter()
+                .position(is_match)
+                .map(|pos| pos + after)
+        };
+        let Some(pos) = found else {
+            return false;
+        };
+
+        self.current_match = Some(lines.len() - 1 - visible[pos]);
+        // Matches outside of the view are centered
+        if !(end.saturating_sub(self.height)..end).contains(&pos) {
+            let end = (pos + self.height / 2 + 1).clamp(self.min_end(&visible), visible.len());
+            self.set_end(&visible, lines.len(), end);
+        }
+        true
+    }
+
+    /// Returns the position in `visible` after the last displayed line
+    fn end(&self, visible: &[usize], len: usize) -> usize {
+        let end = match self.scroll {
+            None => visible.len(),
+            Some(scroll) => visible.partition_point(|idx| idx + scroll < len),
+        };
+        end.max(self.min_end(visible))
+    }
+
+    /// Returns the smallest end of the view, which is reached when scrolled to the top
+    fn min_end(&self, visible: &[usize]) -> usize {
+        self.height.max(1).min(visible.len())
+    }
+
+    /// Scrolls so that the view ends before the position `end` in `visible`
+    fn set_end(&mut self, visible: &[usize], len: usize, end: usize) {
+        let scroll = match end.checked_sub(1) {
+            Some(last) => len - 1 - visible[last],
+            None => len,
+        };
+        self.scroll = Some(scroll);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(num_lines: usize) -> Lines {
+        let mut lines = RingBuffer::new(100);
+        for idx in 0..num_lines {
+            let line = if idx % 10 == 0 {
+                format!("Epoch {idx}")
+            } else {
+                format!("step {idx} loss 1.0")
+            };
+            lines.add((LineSource::Stdout, line));
+        }
+        lines
+    }
+
+    fn view(height: usize) -> LogView {
+        LogView {
+            height,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn scroll() {
+        let mut lines = lines(50);
+        let mut view = view(10);
+        assert_eq!(view.window(&lines), (40..50).collect::<Vec<_>>());
+
+        view.scroll_by(&lines, -5);
+        assert_eq!(view.scroll, Some(5));
+        assert_eq!(view.window(&lines), (35..45).collect::<Vec<_>>());
+
+        // The view stays on its lines while new ones arrive
+        lines.add((LineSource::Stdout, "new".into()));
+        view.line_added();
+        assert_eq!(view.window(&lines), (35..45).collect::<Vec<_>>());
+
+        view.scroll_by(&lines, isize::MIN);
+        assert_eq!(view.window(&lines), (0..10).collect::<Vec<_>>());
+        view.scroll_by(&lines, -1);
+        assert_eq!(view.window(&lines), (0..10).collect::<Vec<_>>());
+
+        view.scroll_by(&lines, isize::MAX);
+        assert_eq!(view.scroll, None);
+        assert_eq!(view.window(&lines), (41..51).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn short_buffer() {
+        let lines = lines(3);
+        let mut view = view(10);
+        view.scroll_by(&lines, -5);
+        assert_eq!(view.scroll, None);
+        assert_eq!(view.window(&lines), [0, 1, 2]);
+        assert!(view.window(&RingBuffer::new(5)).is_empty());
+    }
+
+    #[test]
+    fn search() {
+        let lines = lines(50);
+        let mut view = view(5);
+        view.search = prompt_regex("epoch").unwrap();
+
+        assert!(view.find(&lines, true));
+        assert_eq!(view.current_match(&lines), Some(40));
+        assert_eq!(view.window(&lines), (38..43).collect::<Vec<_>>());
+        assert!(view.find(&lines, true));
+        assert_eq!(view.current_match(&lines), Some(30));
+        assert!(view.find(&lines, false));
+        assert_eq!(view.current_match(&lines), Some(40));
+        assert!(!view.find(&lines, false));
+        assert_eq!(view.current_match(&lines), Some(40));
+
+        view.search = prompt_regex("Step").unwrap();
+        assert!(!view.find(&lines, true));
+        assert!(prompt_regex("(").is_err());
+        assert!(prompt_regex("").unwrap().is_none());
+    }
+
+    #[test]
+    fn filter() {
+        let lines = lines(50);
+        let mut view = view(3);
+        view.filter = prompt_regex("epoch").unwrap();
+        assert_eq!(view.visible(&lines), [0, 10, 20, 30, 40]);
+        assert_eq!(view.window(&lines), [20, 30, 40]);
+
+        view.scroll_by(&lines, -1);
+        assert_eq!(view.window(&lines), [10, 20, 30]);
+        view.search = prompt_regex("0").unwrap();
+        view.current_match = None;
+        assert!(view.find(&lines, false));
+        assert_eq!(view.current_match(&lines), Some(10));
+    }
+}
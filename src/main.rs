@@ -1,19 +1,131 @@
 use anyhow::Result;
-use clap::Parser;
-use crossterm::event::KeyCode;
+use clap::{parser::ValueSource, CommandFactory, FromArgMatches, Parser};
+use crossterm::event::{KeyCode, KeyModifiers};
 use ezboard::{
     app::App,
     event::{Event, EventStream},
+    export::{export, ExportFormat},
+    input::{exit_code, forward_signals, interrupt, resolve_inputs, Tee},
+    listener::{parse_socket_addr, ListenAddr},
+    parser::{read_pattern_file, MetricParser, XAxis, DEFAULT_STEP_FIELDS},
+    server::Server,
+    session::{self, DEFAULT_CHECKPOINT_INTERVAL},
+    smoothing::SmoothingMode,
+    snapshot::{self, SnapshotFormat},
+    stats::{summary_json, summary_table},
+    tfevents,
     tui::Tui,
+    ui::UiState,
 };
 use ratatui::{backend::CrosstermBackend, Terminal};
+use regex::Regex;
+use tokio::task::JoinHandle;
 
-use std::path::PathBuf;
+use std::{
+    io::{ErrorKind, Write},
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    process::ExitCode,
+    time::{Duration, Instant},
+};
 
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = None)]
 struct Cli {
-    path: Option<PathBuf>,
+    /// Log files to read instead of stdin, as `path` or `name=path`. Globs like
+    /// `runs/*/train.log` are expanded. Several files are compared as separate runs.
+    /// TensorBoard event files and log directories containing them are loaded as well
+    #[clap(value_name = "PATH")]
+    paths: Vec<String>,
+
+    /// Command to run after `--`, its stdout and stderr are read instead of a file or stdin.
+    /// ezboard exits with the exit code of the command. Can be combined with event files
+    #[clap(last = true, value_name = "COMMAND")]
+    command: Vec<String>,
+
+    /// Forward Ctrl-C and termination signals to the command instead of quitting
+    #[clap(long, requires = "command")]
+    forward_signals: bool,
+
+    /// Keep reading the file as it grows, surviving truncation and log rotation
+    #[clap(long, short, requires = "paths", conflicts_with = "exit_on_eof")]
+    follow: bool,
+
+    /// Quit when the input ends and print a summary of all metrics
+    #[clap(long)]
+    exit_on_eof: bool,
+
+    /// Receive metrics as JSON lines like `{"key": "loss", "step": 10, "value": 0.3}` on a
+    /// unix socket (`unix:PATH`) or a TCP or UDP port on localhost (`tcp:PORT`, `udp:PORT`).
+    /// Can be given multiple times
+    #[clap(long, value_name = "ADDR")]
+    listen: Vec<ListenAddr>,
+
+    /// Serve a web dashboard at `http://ADDR` and the latest values of all series for
+    /// Prometheus at `http://ADDR/metrics`. ADDR is a port on localhost or an address like
+    /// `0.0.0.0:9100`
+    #[clap(long, value_name = "ADDR", value_parser = parse_socket_addr, conflicts_with = "no_tui")]
+    serve: Option<SocketAddr>,
+
+    /// Write every input line to this file, so the run can be opened again later
+    #[clap(long, value_name = "PATH")]
+    tee: Option<PathBuf>,
+
+    /// Prefix the lines written with `--tee` with the time they were read
+    #[clap(long, requires = "tee")]
+    tee_timestamps: bool,
+
+    /// Write all series to this file when quitting, as CSV or JSON depending on the
+    /// extension. Also used by the export hotkey `e`
+    #[clap(long, value_name = "PATH")]
+    export: Option<PathBuf>,
+
+    /// Write the current view to this file when quitting, or after reading the input with
+    /// `--no-tui`. The extension selects plain text (.txt), ANSI colors (.ans) or SVG (.svg).
+    /// Also used by the snapshot hotkey `w`
+    #[clap(long, value_name = "PATH")]
+    snapshot: Option<PathBuf>,
+
+    /// Size of snapshots in terminal cells
+    #[clap(long, value_name = "WIDTHxHEIGHT", default_value = "120x30", value_parser = parse_size)]
+    snapshot_size: (u16, u16),
+
+    /// Show all metrics in the grid view in the snapshot written by `--snapshot`
+    #[clap(long, requires = "snapshot")]
+    snapshot_grid: bool,
+
+    /// Periodically save all series, the buffered lines and the view settings to this file,
+    /// so they can be restored with `--resume`
+    #[clap(long, value_name = "PATH")]
+    session: Option<PathBuf>,
+
+    /// Restore a session saved with `--session` and continue it with the current input.
+    /// Steps up to the last saved one are skipped. Checkpoints are written back to the same
+    /// file unless `--session` is given. Explicitly given view settings replace the saved ones
+    #[clap(long, value_name = "SESSION")]
+    resume: Option<PathBuf>,
+
+    /// Seconds between two session checkpoints
+    #[clap(long, value_name = "SECONDS", default_value_t = DEFAULT_CHECKPOINT_INTERVAL.as_secs(), value_parser = clap::value_parser!(u64).range(1..))]
+    checkpoint_interval: u64,
+
+    /// Read the whole input without a terminal and print statistics of all metrics
+    #[clap(long, alias = "summary", conflicts_with_all = ["follow", "exit_on_eof"])]
+    no_tui: bool,
+
+    /// Print the statistics of `--no-tui` as JSON
+    #[clap(long, requires = "no_tui")]
+    json: bool,
+
+    /// Number of last values used for the mean in the statistics of `--no-tui`, and for
+    /// the mean, standard deviation and slope in the statistics panel
+    #[clap(long, default_value = "10", value_parser = clap::value_parser!(u64).range(1..))]
+    mean_window: u64,
+
+    /// Regex of the metrics whose best value in the statistics panel is the highest, all
+    /// others are minimized. By default metrics like accuracy or reward are maximized
+    #[clap(long, value_name = "REGEX", value_parser = Regex::new)]
+    maximize: Option<Regex>,
 
     /// Render interval in milliseconds
     #[clap(long, default_value = "100")]
@@ -23,52 +135,329 @@ struct Cli {
     #[clap(long, default_value = "100")]
     line_buffer_length: usize,
 
-    /// Exponential moving average smoothing factor between 0 (constant) and 1 (no smoothing)
+    /// Exponential moving average smoothing factor between 0 (constant) and 1 (no smoothing).
+    /// Can be changed at runtime with `[` and `]`
     #[clap(long, short, default_value = "1.0", value_parser = ranged_float)]
     ema_factor: f64,
+
+    /// Smoothing algorithm, can be cycled at runtime with `m`
+    #[clap(long, value_enum, default_value_t = SmoothingMode::Ema)]
+    smoothing: SmoothingMode,
+
+    /// Number of values used by the window based smoothing algorithms
+    #[clap(long, default_value = "10", value_parser = clap::value_parser!(u64).range(1..))]
+    smoothing_window: u64,
+
+    /// Use a logarithmic y-axis (symmetric log if a series has values <= 0)
+    #[clap(long)]
+    log_scale: bool,
+
+    /// Custom regex for metric extraction with named `key` and `value` groups,
+    /// e.g. "(?P<key>lr)=(?P<value>[0-9.e-]+)". Can be given multiple times
+    #[clap(long = "pattern", value_name = "REGEX")]
+    patterns: Vec<String>,
+
+    /// File with additional patterns, one regex per line
+    #[clap(long, value_name = "PATH")]
+    pattern_file: Option<PathBuf>,
+
+    /// Additional key suffixes recognised by the built-in pattern (e.g. lr,perplexity)
+    #[clap(long = "key-suffix", value_name = "SUFFIX", value_delimiter = ',')]
+    key_suffixes: Vec<String>,
+
+    /// Only use the custom patterns instead of the built-in one
+    #[clap(long)]
+    no_default_pattern: bool,
+
+    /// Fields of JSON lines used as x value, in order of preference
+    #[clap(long = "step-field", value_name = "FIELD", value_delimiter = ',', default_values_t = DEFAULT_STEP_FIELDS.map(String::from))]
+    step_fields: Vec<String>,
+
+    /// Counter in the log lines used as x value. Falls back to one after the
+    /// last x value of the metric if the counter isn't found in a line
+    #[clap(long, value_enum, default_value_t = XAxis::Auto)]
+    x_axis: XAxis,
 }
 
 fn ranged_float(s: &str) -> Result<f64, String> {
     let f: f64 = s.parse().map_err(|_| format!("`{s}` isn't a number"))?;
-    if f < 0.0 || f > 1.0 {
+    if !(0.0..=1.0).contains(&f) {
         return Err("Only numbers between 0 and 1 are supported".into());
     }
 
     Ok(f)
 }
 
+fn parse_size(s: &str) -> Result<(u16, u16), String> {
+    let size = s.split_once('x').and_then(|(width, height)| {
+        let size = (width.parse().ok()?, height.parse().ok()?);
+        Some(size).filter(|(width, height)| *width > 0 && *height > 0)
+    });
+    size.ok_or_else(|| format!("`{s}` isn't a size like 120x30"))
+}
+
 #[tokio::main]
-async fn main() -> Result<()> {
-    let args = Cli::parse();
+async fn main() -> Result<ExitCode> {
+    let matches = Cli::command().get_matches();
+    let args = Cli::from_arg_matches(&matches).unwrap_or_else(|err| err.exit());
 
-    let backend = CrosstermBackend::new(std::io::stdout());
-    let terminal = Terminal::new(backend)?;
+    let mut patterns = args.patterns.clone();
+    if let Some(path) = &args.pattern_file {
+        patterns.extend(read_pattern_file(path)?);
+    }
+    let parser = MetricParser::new(&patterns, &args.key_suffixes, !args.no_default_pattern)?
+        .with_step_fields(args.step_fields.clone())
+        .with_x_axis(args.x_axis);
 
-    let event_stream = EventStream::new(args.render_interval, &args.path).await;
+    // TensorBoard event files are loaded at once, after the runs read line by line
+    let mut runs = Vec::new();
+    let mut paths = Vec::new();
+    let mut event_runs = Vec::new();
+    for (name, path) in resolve_inputs(&args.paths)? {
+        if tfevents::is_event_input(&path) {
+            event_runs.extend(tfevents::find_runs(&name, &path)?);
+        } else {
+            runs.push(name);
+            paths.push(path);
+        }
+    }
+    if let Some(program) = args.command.first() {
+        if !paths.is_empty() {
+            let message = "Only TensorBoard event files can be read together with a command";
+            Cli::command()
+                .error(clap::error::ErrorKind::ArgumentConflict, message)
+                .exit();
+        }
+        // The command is read as the first run, named after the program
+        let name = Path::new(program)
+            .file_name()
+            .map_or(program.clone(), |name| name.to_string_lossy().into_owned());
+        runs.push(name.replace('@', "_"));
+    }
+    let tee = match &args.tee {
+        Some(path) => Some(Tee::create(path, args.tee_timestamps).await?),
+        None => None,
+    };
+    let event_stream = if args.no_tui {
+        EventStream::without_terminal(&paths, args.follow, &args.command).await?
+    } else {
+        EventStream::new(args.render_interval, &paths, args.follow, &args.command).await?
+    }
+    .with_tee(tee);
+    let event_stream = if paths.is_empty() && !event_runs.is_empty() {
+        event_stream.without_stdin()
+    } else {
+        event_stream
+    }
+    .with_listeners(&args.listen)
+    .await?;
+    if let Some(pid) = event_stream.child_id().filter(|_| args.forward_signals) {
+        forward_signals(pid)?;
+    }
 
     let mut app = App::new(args.line_buffer_length, args.ema_factor);
+    app.parser = parser;
+    if let Some(path) = &args.export {
+        ExportFormat::from_path(path)?;
+        app.export_path = path.clone();
+    }
+    if let Some(path) = &args.snapshot {
+        SnapshotFormat::from_path(path)?;
+        app.snapshot_path = path.clone();
+    }
+    app.snapshot_size = args.snapshot_size;
+    app.state.follow = args.follow;
+    app.state.stats_window = args.mean_window as usize;
+    app.state.maximize = args.maximize.clone();
+    let first_event_run = runs.len();
+    runs.extend(event_runs.iter().map(|(name, _)| name.clone()));
+    if runs.len() > 1 {
+        app.state.runs = runs;
+    }
+    for (idx, (_, files)) in event_runs.iter().enumerate() {
+        tfevents::load(&mut app.state, first_event_run + idx, files)?;
+    }
+    if let Some(path) = &args.resume {
+        session::restore(&mut app.state, path)?;
+    }
+    // The settings of a resumed session apply unless they are given explicitly
+    let apply = |id: &str| {
+        args.resume.is_none() || matches.value_source(id) == Some(ValueSource::CommandLine)
+    };
+    if apply("log_scale") {
+        app.state.log_scale = args.log_scale;
+    }
+    if apply("ema_factor") {
+        app.state.smoothing.ema_factor = args.ema_factor;
+    }
+    if apply("smoothing") {
+        app.state.smoothing.mode = args.smoothing;
+    }
+    if apply("smoothing_window") {
+        app.state.smoothing.window = args.smoothing_window as usize;
+    }
+
+    if args.no_tui {
+        return summarize(&args, app, event_stream).await;
+    }
+
+    let mut server = match args.serve {
+        Some(addr) => Some(Server::bind(addr).await?),
+        None => None,
+    };
+
+    let backend = CrosstermBackend::new(std::io::stdout());
+    let terminal = Terminal::new(backend)?;
     let mut tui = Tui::new(terminal, event_stream);
     tui.init()?;
 
+    let checkpoint_interval = Duration::from_secs(args.checkpoint_interval);
+    let mut last_checkpoint = Instant::now();
+    let mut checkpoint: Option<JoinHandle<Result<()>>> = None;
     while app.running {
         let event = tui.event_stream.next().await;
 
         match event {
-            Event::Tick => tui.draw(&mut app)?,
-            Event::LineRead(line) => app.process_line(&line),
+            Event::Tick => {
+                if let Some(task) = checkpoint.take_if(|task| task.is_finished()) {
+                    if let Err(err) = task
+                        .await
+                        .map_err(anyhow::Error::from)
+                        .and_then(|saved| saved)
+                    {
+                        let notice = format!("Saving the session failed: {err:#}");
+                        app.state.notice = Some((Instant::now(), notice));
+                    }
+                }
+                if let Some(path) = session_path(&args) {
+                    if checkpoint.is_none() && last_checkpoint.elapsed() >= checkpoint_interval {
+                        last_checkpoint = Instant::now();
+                        checkpoint = Some(session::save_in_background(&app.state, path));
+                    }
+                }
+                if let Some(server) = &mut server {
+                    server.publish(&app.state);
+                }
+                tui.draw(&mut app)?;
+            }
+            Event::LineRead(run, source, line) => app.process_line_from(run, source, &line),
+            Event::Message(message) => app.process_message(&message),
             Event::Key(key) => match key.code {
+                KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                    match tui.event_stream.child_id() {
+                        Some(pid) if args.forward_signals => interrupt(pid),
+                        _ => {
+                            app.quit();
+                            break;
+                        }
+                    }
+                }
+                // Typed into the search or filter of the passthrough view
+                _ if app.state.log_view.prompt.is_some() => app.handle_keypress(key.code),
                 KeyCode::Char('q') | KeyCode::Char('Q') => {
                     app.quit();
                     break;
                 }
                 _ => app.handle_keypress(key.code),
             },
-            _ => (),
+            Event::End(exit_status) => {
+                app.finish_input(exit_status);
+                if args.exit_on_eof {
+                    app.quit();
+                }
+            }
         }
     }
 
     tui.exit()?;
 
-    std::io::copy(&mut std::io::stdin(), &mut std::io::stdout())?;
-    Ok(())
+    // The last checkpoint must not replace the final session
+    if let Some(task) = checkpoint {
+        let _ = task.await;
+    }
+    if let Some(path) = session_path(&args) {
+        session::save(&app.state, path)?;
+    }
+    if let Some(path) = &args.export {
+        export(&app.state, path)?;
+    }
+    write_snapshot(&args, &mut app)?;
+
+    if args.exit_on_eof && app.state.finished_at.is_some() {
+        print!("{}", app.summary());
+    }
+
+    // Pass the rest of piped input or the output of the still running command through,
+    // the rest of a file isn't printed
+    let exit_status = match app.state.exit_status {
+        Some(status) => Some(status),
+        None if paths.is_empty() => tui.event_stream.pass_through().await?,
+        None => None,
+    };
+    Ok(exit_status.map_or(ExitCode::SUCCESS, |status| {
+        ExitCode::from(exit_code(status))
+    }))
+}
+
+/// Returns the file checkpoints are written to, `--session` or else the resumed one
+fn session_path(args: &Cli) -> Option<&Path> {
+    args.session.as_deref().or(args.resume.as_deref())
+}
+
+/// Writes the snapshot given by `--snapshot`
+fn write_snapshot(args: &Cli, app: &mut App) -> Result<()> {
+    let Some(path) = &args.snapshot else {
+        return Ok(());
+    };
+    if args.snapshot_grid {
+        app.state.ui_state = UiState::Grid;
+    }
+    // Transient messages don't belong into the snapshot
+    app.state.notice = None;
+    snapshot::write(app, path, args.snapshot_size)
+}
+
+/// Reads the whole input without a terminal and prints the statistics of all series
+///
+/// Ctrl-C ends the input early, e.g. if only listeners are read, unless it's forwarded to
+/// the command.
+async fn summarize(args: &Cli, mut app: App, mut event_stream: EventStream) -> Result<ExitCode> {
+    let interruptible = event_stream.child_id().is_none() || !args.forward_signals;
+    let ctrl_c = tokio::signal::ctrl_c();
+    tokio::pin!(ctrl_c);
+    let exit_status = loop {
+        let event = tokio::select! {
+            event = event_stream.next() => event,
+            _ = &mut ctrl_c, if interruptible => break None,
+        };
+        match event {
+            Event::LineRead(run, source, line) => app.process_line_from(run, source, &line),
+            Event::Message(message) => app.process_message(&message),
+            Event::End(exit_status) => break exit_status,
+            Event::Tick | Event::Key(_) => {}
+        }
+    };
+
+    if let Some(path) = session_path(args) {
+        session::save(&app.state, path)?;
+    }
+    if let Some(path) = &args.export {
+        export(&app.state, path)?;
+    }
+    write_snapshot(args, &mut app)?;
+
+    let window = args.mean_window as usize;
+    let summary = if args.json {
+        format!("{:#}\n", summary_json(&app.state, window))
+    } else {
+        summary_table(&app.state, window)
+    };
+    // A closed pipe (e.g. `| head`) isn't an error for a summary meant for scripts
+    match std::io::stdout().write_all(summary.as_bytes()) {
+        Err(err) if err.kind() != ErrorKind::BrokenPipe => return Err(err.into()),
+        _ => {}
+    }
+    Ok(exit_status.map_or(ExitCode::SUCCESS, |status| {
+        ExitCode::from(exit_code(status))
+    }))
 }
@@ -0,0 +1,554 @@
+use anyhow::{bail, Context, Result};
+use clap::ValueEnum;
+use regex::Regex;
+use serde_json::Value;
+
+/// Key suffixes the built-in pattern recognises
+const DEFAULT_SUFFIXES: [&str; 5] = ["loss", "error", "cost", "acc", "accuracy"];
+
+/// Fields of a JSON line that are used as x value, in order of preference
+pub const DEFAULT_STEP_FIELDS: [&str; 3] = ["step", "global_step", "epoch"];
+
+/// Metrics parsed from a single JSON object line
+#[derive(Debug, PartialEq)]
+pub struct JsonRecord {
+    /// Value of the step field, if the object contains one
+    pub step: Option<f64>,
+    /// All other numeric fields, nested keys are joined with `/`
+    pub values: Vec<(String, f64)>,
+}
+
+/// Counter in a line that is used as x value
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum XAxis {
+    /// Finest counter found in the line (step, iteration, progress, epoch). A counter
+    /// with a total that restarts every epoch, like `[12/500]` or `Step [100/600]`,
+    /// continues across epochs if the epoch is in the same line
+    #[default]
+    Auto,
+    /// Number of values already stored for the metric
+    Counter,
+    /// `Step 1200`, `step=1200`, `global_step: 1200`, `Step [100/600]`
+    Step,
+    /// `iter=300`, `Iteration 300`, `Iter [30/100]`
+    Iteration,
+    /// `Epoch 5/100`, `epoch: 5`, `Epoch [5/100]`
+    Epoch,
+    /// `[12/500]`, `[1280/60000 (2%)]`
+    Progress,
+}
+
+/// Counters that are found in lines, all x axes but `Auto` and `Counter`
+const LINE_COUNTERS: [XAxis; 4] = [XAxis::Step, XAxis::Iteration, XAxis::Progress, XAxis::Epoch];
+
+impl XAxis {
+    /// Returns the counters used for this x axis, in order of preference
+    fn counters(self) -> &'static [XAxis] {
+        match self {
+            XAxis::Auto => &LINE_COUNTERS,
+            XAxis::Counter => &[],
+            XAxis::Step => &[XAxis::Step],
+            XAxis::Iteration => &[XAxis::Iteration],
+            XAxis::Epoch => &[XAxis::Epoch],
+            XAxis::Progress => &[XAxis::Progress],
+        }
+    }
+
+    /// Returns the pattern of the counter, with its value in the first group and its
+    /// total in the second one if there is one, e.g. in `Step [100/600]`
+    ///
+    /// Returns `None` for the x axes that aren't a counter in the line.
+    fn pattern(self) -> Option<Regex> {
+        let pattern = match self {
+            XAxis::Step => r"(?i)\b(?:global_)?step\b",
+            XAxis::Iteration => r"(?i)\b(?:iter|iteration)\b",
+            XAxis::Epoch => r"(?i)\bepoch\b",
+            XAxis::Progress => {
+                let pattern =
+                    r"\[[\s--\n]*([0-9]+)[\s--\n]*/[\s--\n]*([0-9]+)(?:[\s--\n][^\]\n]*)?\]";
+                return Some(Regex::new(pattern).expect("Step pattern must compile"));
+            }
+            XAxis::Auto | XAxis::Counter => return None,
+        };
+        // The keyword is followed by the value, optionally in brackets and with a total
+        let value = r"[\s--\n]*[:=]?[\s--\n]*\[?[\s--\n]*([0-9]+)(?:[\s--\n]*/[\s--\n]*([0-9]+))?";
+        Some(Regex::new(&format!("{pattern}{value}")).expect("Step pattern must compile"))
+    }
+}
+
+/// Extracts `(key, value)` pairs from raw input lines
+///
+/// Combines the built-in heuristic (keys ending in one of the known suffixes)
+/// with user supplied regexes. All patterns are compiled once on construction.
+#[derive(Debug, Clone)]
+pub struct MetricParser {
+    /// Built-in pattern, `None` if it was disabled
+    builtin: Option<Regex>,
+    /// User supplied patterns with named `key` and `value` groups
+    custom: Vec<Regex>,
+    /// Fields of JSON lines used as x value
+    step_fields: Vec<String>,
+    /// Counter used as x value
+    x_axis: XAxis,
+    /// Patterns of all counters in lines, see [`XAxis::pattern`]
+    counter_patterns: Vec<(XAxis, Regex)>,
+}
+
+impl Default for MetricParser {
+    fn default() -> Self {
+        Self {
+            builtin: Some(builtin_pattern(&[]).expect("Built-in pattern must compile")),
+            custom: Vec::new(),
+            step_fields: DEFAULT_STEP_FIELDS.iter().map(|f| f.to_string()).collect(),
+            x_axis: XAxis::default(),
+            counter_patterns: LINE_COUNTERS
+                .iter()
+                .filter_map(|counter| Some((*counter, counter.pattern()?)))
+                .collect(),
+        }
+    }
+}
+
+impl MetricParser {
+    /// Constructs a new [`MetricParser`]
+    ///
+    /// * `patterns` - regexes that need a named `key` and a named `value` group
+    /// * `suffixes` - additional key suffixes for the built-in pattern
+    /// * `builtin` - whether the built-in pattern is used at all
+    pub fn new(patterns: &[String], suffixes: &[String], builtin: bool) -> Result<Self> {
+        let builtin = if builtin {
+            Some(builtin_pattern(suffixes)?)
+        } else {
+            None
+        };
+
+        let custom = patterns
+            .iter()
+            .map(|p| custom_pattern(p))
+            .collect::<Result<Vec<_>>>()?;
+
+        if builtin.is_none() && custom.is_empty() {
+            bail!("The built-in pattern is disabled, but no custom pattern was given");
+        }
+
+        Ok(Self {
+            builtin,
+            custom,
+            ..Default::default()
+        })
+    }
+
+    /// Sets the fields of JSON lines that are used as x value, in order of preference
+    pub fn with_step_fields(mut self, step_fields: Vec<String>) -> Self {
+        self.step_fields = step_fields;
+        self
+    }
+
+    /// Sets the counter that is used as x value
+    pub fn with_x_axis(mut self, x_axis: XAxis) -> Self {
+        self.x_axis = x_axis;
+        self
+    }
+
+    /// Returns the step of a line according to the configured [`XAxis`]
+    ///
+    /// Returns `None` if the line doesn't contain the counter, in which case
+    /// the number of stored values should be used instead.
+    pub fn extract_step(&self, line: &str) -> Option<f64> {
+        self.x_axis.counters().iter().find_map(|counter| {
+            let (step, total) = self.find_counter(*counter, line)?;
+            if self.x_axis != XAxis::Auto || *counter == XAxis::Epoch {
+                return Some(step);
+            }
+
+            // Batches of later epochs are placed after the ones of earlier epochs,
+            // which are counted from 1
+            let epoch = self.find_counter(XAxis::Epoch, line);
+            match (total, epoch) {
+                (Some(total), Some((epoch, _))) => Some((epoch - 1.0) * total + step),
+                _ => Some(step),
+            }
+        })
+    }
+
+    /// Returns the value of `counter` in `line` and its total, if one is given
+    ///
+    /// A bracket right after a keyword, like `[1/10]` in `Epoch [1/10]`, belongs to the
+    /// keyword and isn't a progress counter.
+    fn find_counter(&self, counter: XAxis, line: &str) -> Option<(f64, Option<f64>)> {
+        let pattern = |counter| {
+            self.counter_patterns
+                .iter()
+                .find_map(|(other, pattern)| (*other == counter).then_some(pattern))
+        };
+        let caps = if counter == XAxis::Progress {
+            let tagged: Vec<_> = [XAxis::Step, XAxis::Iteration, XAxis::Epoch]
+                .into_iter()
+                .filter_map(pattern)
+                .flat_map(|pattern| pattern.find_iter(line).map(|tag| tag.range()))
+                .collect();
+            pattern(counter)?.captures_iter(line).find(|caps| {
+                let range = caps.get(0).map_or(0..0, |progress| progress.range());
+                !tagged
+                    .iter()
+                    .any(|tag| tag.start < range.end && range.start < tag.end)
+            })?
+        } else {
+            pattern(counter)?.captures(line)?
+        };
+        let value = caps.get(1)?.as_str().parse().ok()?;
+        let total = caps.get(2).and_then(|total| total.as_str().parse().ok());
+        Some((value, total))
+    }
+
+    /// Parses a line containing a JSON object
+    ///
+    /// Returns `None` if the line isn't a JSON object. Nested objects are
+    /// flattened, non-numeric fields are ignored.
+    pub fn extract_json(&self, line: &str) -> Option<JsonRecord> {
+        let line = line.trim();
+        if !line.starts_with('{') {
+            return None;
+        }
+        let Ok(Value::Object(object)) = serde_json::from_str::<Value>(line) else {
+            return None;
+        };
+
+        let mut values = Vec::new();
+        for (key, value) in &object {
+            flatten(key.clone(), value, &mut values);
+        }
+
+        let step = self.step_fields.iter().find_map(|field| {
+            let idx = values.iter().position(|(key, _)| key == field)?;
+            Some(values.remove(idx).1)
+        });
+        let step = step.filter(|_| self.x_axis != XAxis::Counter);
+
+        Some(JsonRecord { step, values })
+    }
+
+    /// Parses a metric message received by a listener
+    ///
+    /// Messages are JSON objects with a `key`, a numeric `value` and an optional `step`,
+    /// e.g. `{"key": "loss", "step": 10, "value": 0.3}`. Other objects are parsed like
+    /// JSON lines. Returns `None` if the message isn't a JSON object.
+    pub fn extract_message(&self, message: &str) -> Option<JsonRecord> {
+        let Ok(Value::Object(object)) = serde_json::from_str::<Value>(message.trim()) else {
+            return None;
+        };
+        let key = object.get("key").and_then(Value::as_str);
+        let value = object.get("value").and_then(Value::as_f64);
+        let (Some(key), Some(value)) = (key, value) else {
+            return self.extract_json(message);
+        };
+
+        let step = object.get("step").and_then(Value::as_f64);
+        Some(JsonRecord {
+            step: step.filter(|_| self.x_axis != XAxis::Counter),
+            values: vec![(key.to_owned(), value)],
+        })
+    }
+
+    /// Returns all `(key, value)` pairs of a line in the order they appear
+    ///
+    /// A value matched by several patterns is only reported once.
+    pub fn extract(&self, line: &str) -> Vec<(String, f64)> {
+        let mut matches = Vec::new();
+
+        for pattern in self.builtin.iter().chain(self.custom.iter()) {
+            for caps in pattern.captures_iter(line) {
+                let (Some(key), Some(val)) = (caps.name("key"), caps.name("value")) else {
+                    continue;
+                };
+                let overlaps = matches
+                    .iter()
+                    .any(|(start, end, _, _)| val.start() < *end && *start < val.end());
+                if overlaps {
+                    continue;
+                }
+                let Ok(parsed) = val.as_str().parse::<f64>() else {
+                    continue;
+                };
+                matches.push((val.start(), val.end(), key.as_str().to_owned(), parsed));
+            }
+        }
+
+        matches.sort_by_key(|(start, _, _, _)| *start);
+        matches
+            .into_iter()
+            .map(|(_, _, key, val)| (key, val))
+            .collect()
+    }
+}
+
+fn flatten(prefix: String, value: &Value, values: &mut Vec<(String, f64)>) {
+    match value {
+        Value::Number(n) => {
+            if let Some(n) = n.as_f64() {
+                values.push((prefix, n));
+            }
+        }
+        Value::Object(object) => {
+            for (key, value) in object {
+                flatten(format!("{prefix}/{key}"), value, values);
+            }
+        }
+        _ => (),
+    }
+}
+
+fn builtin_pattern(suffixes: &[String]) -> Result<Regex> {
+    let suffixes: Vec<String> = DEFAULT_SUFFIXES
+        .iter()
+        .map(|s| s.to_string())
+        .chain(suffixes.iter().map(|s| regex::escape(s)))
+        .collect();
+
+    let pattern = format!(
+        r"(?i)(?P<key>\b\w*?(?:{})\b)[\s--\n]*:?[\s--\n]*(?P<value>-?[0-9]+(?:\.[0-9]+)?(?:e-?[0-9]+)?)",
+        suffixes.join("|")
+    );
+
+    Regex::new(&pattern).context("Invalid key suffix")
+}
+
+fn custom_pattern(pattern: &str) -> Result<Regex> {
+    let regex = Regex::new(pattern).with_context(|| format!("Invalid pattern `{pattern}`"))?;
+
+    for group in ["key", "value"] {
+        if !regex.capture_names().flatten().any(|name| name == group) {
+            bail!("Pattern `{pattern}` is missing the named capture group `(?P<{group}>...)`");
+        }
+    }
+
+    Ok(regex)
+}
+
+/// Reads patterns from a file, one regex per line
+///
+/// Empty lines and lines starting with `#` are ignored.
+pub fn read_pattern_file(path: &std::path::Path) -> Result<Vec<String>> {
+    let content = std::fs::read_to_string(path)
+        .with_context(|| format!("Could not read pattern file {}", path.display()))?;
+
+    Ok(content
+        .lines()
+        .map(str::trim)
+        .filter(|l| !l.is_empty() && !l.starts_with('#'))
+        .map(str::to_owned)
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn custom_patterns() {
+        let parser = MetricParser::new(
+            &[
+                r"(?P<key>lr)=(?P<value>[0-9.e-]+)".into(),
+                r"(?P<key>grad_norm):\s*(?P<value>[0-9.]+)".into(),
+            ],
+            &[],
+            true,
+        )
+        .unwrap();
+
+        let matches = parser.extract("loss 0.5 lr=1e-3 grad_norm: 2.5");
+        assert_eq!(
+            matches,
+            vec![
+                ("loss".into(), 0.5),
+                ("lr".into(), 1e-3),
+                ("grad_norm".into(), 2.5)
+            ]
+        );
+    }
+
+    #[test]
+    fn extra_suffixes() {
+        let parser = MetricParser::new(
+            &[],
+            &["perplexity".into(), "bleu".into(), "mAP".into()],
+            true,
+        )
+        .unwrap();
+
+        let matches = parser.extract("val_perplexity 12.5, BLEU: 30.1, mAP 0.75");
+        assert_eq!(
+            matches,
+            vec![
+                ("val_perplexity".into(), 12.5),
+                ("BLEU".into(), 30.1),
+                ("mAP".into(), 0.75)
+            ]
+        );
+    }
+
+    #[test]
+    fn no_duplicates() {
+        let parser =
+            MetricParser::new(&[r"(?P<key>loss) (?P<value>[0-9.]+)".into()], &[], true).unwrap();
+
+        assert_eq!(parser.extract("loss 1.0"), vec![("loss".into(), 1.0)]);
+    }
+
+    #[test]
+    fn without_builtin() {
+        let parser =
+            MetricParser::new(&[r"(?P<key>lr)=(?P<value>[0-9.]+)".into()], &[], false).unwrap();
+
+        assert_eq!(parser.extract("loss 1.0 lr=0.1"), vec![("lr".into(), 0.1)]);
+    }
+
+    #[test]
+    fn missing_groups() {
+        let err = MetricParser::new(&[r"(?P<key>lr)=([0-9.]+)".into()], &[], true).unwrap_err();
+        assert!(err.to_string().contains("`(?P<value>...)`"));
+
+        let err = MetricParser::new(&[r"lr=(?P<value>[0-9.]+)".into()], &[], true).unwrap_err();
+        assert!(err.to_string().contains("`(?P<key>...)`"));
+
+        assert!(MetricParser::new(&["(".into()], &[], true).is_err());
+        assert!(MetricParser::new(&[], &[], false).is_err());
+    }
+
+    #[test]
+    fn steps() {
+        let parser = MetricParser::default();
+
+        assert_eq!(parser.extract_step("Step 1200 loss 0.5"), Some(1200.0));
+        assert_eq!(parser.extract_step("global_step: 30, loss 0.5"), Some(30.0));
+        assert_eq!(parser.extract_step("Epoch 5/100 Loss: 3.5"), Some(5.0));
+        assert_eq!(parser.extract_step("iter=300 loss=0.1"), Some(300.0));
+        assert_eq!(parser.extract_step("Train [12/500] loss 0.1"), Some(12.0));
+        assert_eq!(parser.extract_step("step_loss 0.1"), None);
+        assert_eq!(parser.extract_step("loss 0.1"), None);
+
+        // Finer counters have precedence over epoch in auto mode
+        assert_eq!(
+            parser.extract_step("Epoch 2 Step 300 loss 0.1"),
+            Some(300.0)
+        );
+        assert_eq!(parser.extract_step("Epoch 2 iter 30 loss 0.1"), Some(30.0));
+    }
+
+    #[test]
+    fn epoch_progress() {
+        // Batches of an epoch must not share the x value of the epoch
+        let parser = MetricParser::default();
+        let first = parser.extract_step("Train Epoch: 1 [1280/60000 (2%)]\tLoss: 0.3");
+        let second = parser.extract_step("Train Epoch: 1 [2560/60000 (4%)]\tLoss: 0.2");
+        let next_epoch = parser.extract_step("Train Epoch: 2 [0/60000 (0%)]\tLoss: 0.1");
+        assert_eq!(first, Some(1280.0));
+        assert_eq!(second, Some(2560.0));
+        assert_eq!(next_epoch, Some(60000.0));
+
+        // Counters with a total right after their keyword
+        let steps = [
+            "Epoch [1/10], Step [100/600], Loss: 0.5",
+            "Epoch [1/10], Step [200/600], Loss: 0.4",
+            "Epoch [2/10], Step [100/600], Loss: 0.3",
+        ];
+        let steps: Vec<_> = steps.iter().map(|line| parser.extract_step(line)).collect();
+        assert_eq!(steps, [Some(100.0), Some(200.0), Some(700.0)]);
+        assert_eq!(parser.extract_step("Epoch [3/10] Loss: 0.3"), Some(3.0));
+        assert_eq!(
+            parser.extract_step("Epoch [2/10] [50/600] Loss: 0.3"),
+            Some(650.0)
+        );
+        let parser = MetricParser::default().with_x_axis(XAxis::Step);
+        assert_eq!(
+            parser.extract_step("Epoch [2/10], Step [100/600], Loss: 0.3"),
+            Some(100.0)
+        );
+
+        let parser = MetricParser::default().with_x_axis(XAxis::Progress);
+        assert_eq!(
+            parser.extract_step("Train Epoch: 1 [1280/60000 (2%)]\tLoss: 0.3"),
+            Some(1280.0)
+        );
+        assert_eq!(parser.extract_step("[12/500 ] loss 0.1"), Some(12.0));
+        assert_eq!(parser.extract_step("[12/500x] loss 0.1"), None);
+    }
+
+    #[test]
+    fn explicit_x_axis() {
+        let line = "Epoch 2 [32/500] Step 300 loss 0.1";
+
+        let parser = MetricParser::default().with_x_axis(XAxis::Epoch);
+        assert_eq!(parser.extract_step(line), Some(2.0));
+        assert_eq!(parser.extract_step("Step 300 loss 0.1"), None);
+
+        let parser = MetricParser::default().with_x_axis(XAxis::Progress);
+        assert_eq!(parser.extract_step(line), Some(32.0));
+
+        let parser = MetricParser::default().with_x_axis(XAxis::Counter);
+        assert_eq!(parser.extract_step(line), None);
+        let record = parser.extract_json(r#"{"step": 10, "loss": 0.3}"#).unwrap();
+        assert_eq!(record.step, None);
+    }
+
+    #[test]
+    fn json_lines() {
+        let parser = MetricParser::default();
+
+        let record = parser
+            .extract_json(r#"{"step": 10, "loss": 0.3, "val": {"acc": 0.9}, "tag": "a"}"#)
+            .expect("Line should be parsed as JSON");
+        assert_eq!(record.step, Some(10.0));
+        assert_eq!(
+            record.values,
+            vec![("loss".into(), 0.3), ("val/acc".into(), 0.9)]
+        );
+
+        let record = parser.extract_json(r#"{"lr": 1e-3}"#).unwrap();
+        assert_eq!(record.step, None);
+        assert_eq!(record.values, vec![("lr".into(), 1e-3)]);
+
+        assert_eq!(parser.extract_json("loss 1.0"), None);
+        assert_eq!(parser.extract_json("{loss 1.0}"), None);
+    }
+
+    #[test]
+    fn messages() {
+        let parser = MetricParser::default();
+
+        let record = parser
+            .extract_message(r#"{"key": "loss", "step": 10, "value": 0.3}"#)
+            .unwrap();
+        assert_eq!(record.step, Some(10.0));
+        assert_eq!(record.values, vec![("loss".into(), 0.3)]);
+
+        let record = parser
+            .extract_message(r#"{"key": "lr", "value": 1e-3}"#)
+            .unwrap();
+        assert_eq!(record.step, None);
+        assert_eq!(record.values, vec![("lr".into(), 1e-3)]);
+
+        let record = parser
+            .extract_message(r#"{"step": 2, "acc": 0.5, "key": "eval"}"#)
+            .unwrap();
+        assert_eq!(record.step, Some(2.0));
+        assert_eq!(record.values, vec![("acc".into(), 0.5)]);
+
+        assert_eq!(parser.extract_message("loss 1.0"), None);
+        assert_eq!(parser.extract_message("[1, 2]"), None);
+    }
+
+    #[test]
+    fn json_step_fields() {
+        let parser = MetricParser::default().with_step_fields(vec!["epoch".into()]);
+
+        let record = parser
+            .extract_json(r#"{"step": 10, "epoch": 2, "loss": 0.3}"#)
+            .unwrap();
+        assert_eq!(record.step, Some(2.0));
+        assert_eq!(
+            record.values,
+            vec![("loss".into(), 0.3), ("step".into(), 10.0)]
+        );
+    }
+}
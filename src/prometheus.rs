@@ -0,0 +1,160 @@
+use crate::app::{AppState, Timeseries};
+
+/// Computes a gauge of a series, `None` leaves the series out
+type Gauge = fn(&Timeseries) -> Option<f64>;
+
+/// Gauges exported per series, with their help text and how they're computed
+const SERIES_GAUGES: [(&str, &str, Gauge); 5] = [
+    ("last", "Last value of the series", |data| {
+        data.get_data().last().map(|(_, val)| *val)
+    }),
+    (
+        "last_step",
+        "Step of the last value of the series",
+        |data| data.get_data().last().map(|(t, _)| *t),
+    ),
+    ("min", "Smallest value of the series", |data| {
+        (!data.is_empty()).then(|| data.get_min())
+    }),
+    ("max", "Largest value of the series", |data| {
+        (!data.is_empty()).then(|| data.get_max())
+    }),
+    ("points", "Number of values of the series", |data| {
+        Some(data.len() as f64)
+    }),
+];
+
+/// Renders all series and input counters in the Prometheus text exposition format
+///
+/// Every series is exported as gauges `ezboard_series_*` labelled by its key and,
+/// with several runs, by metric and run.
+pub fn render(state: &AppState) -> String {
+    let mut text = String::new();
+    let keys = state.sorted_keys();
+    for (name, help, gauge) in SERIES_GAUGES {
+        text += &format!("# HELP ezboard_series_{name} {help}\n");
+        text += &format!("# TYPE ezboard_series_{name} gauge\n");
+        for key in &keys {
+            let Some(value) = gauge(&state.data[key]) else {
+                continue;
+            };
+            text += &format!(
+                "ezboard_series_{name}{{{}}} {}\n",
+                labels(state, key),
+                format_value(value)
+            );
+        }
+    }
+
+    let counters = [
+        (
+            "lines_read",
+            "Number of lines read from the input",
+            state.lines_read,
+        ),
+        (
+            "parse_failures",
+            "Number of JSON lines and messages that are invalid or have no numeric value",
+            state.parse_failures,
+        ),
+    ];
+    for (name, help, value) in counters {
+        text += &format!("# HELP ezboard_{name}_total {help}\n");
+        text += &format!("# TYPE ezboard_{name}_total counter\n");
+        text += &format!("ezboard_{name}_total {value}\n");
+    }
+    text
+}
+
+/// Returns the labels of the series `key`
+fn labels(state: &AppState, key: &str) -> String {
+    let mut labels = format!("series=\"{}\"", escape_label(key));
+    if let (metric, Some(run)) = state.split_key(key) {
+        labels += &format!(
+            ",metric=\"{}\",run=\"{}\"",
+            escape_label(metric),
+            escape_label(run)
+        );
+    }
+    labels
+}
+
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// Formats `value` as Prometheus sample value, which spells infinity `+Inf`
+fn format_value(value: f64) -> String {
+    if value.is_nan() {
+        "NaN".to_owned()
+    } else if value.is_infinite() {
+        if value > 0.0 { "+Inf" } else { "-Inf" }.to_owned()
+    } else {
+        value.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::App;
+
+    #[test]
+    fn exposition() {
+        let mut app = App::new(5, 1.0);
+        app.process_line("Step 1 loss 2.0");
+        app.process_line("Step 2 loss 0.5");
+        app.process_line("starting epoch");
+        app.process_line(r#"{"step": 2, "eval\"acc": 0.5}"#);
+
+        let text = render(&app.state);
+        let lines: Vec<_> = text.lines().collect();
+        assert_eq!(
+            &lines[..4],
+            [
+                "# HELP ezboard_series_last Last value of the series",
+                "# TYPE ezboard_series_last gauge",
+                r#"ezboard_series_last{series="eval\"acc"} 0.5"#,
+                r#"ezboard_series_last{series="loss"} 0.5"#,
+            ]
+        );
+        assert!(lines.contains(&r#"ezboard_series_last_step{series="loss"} 2"#));
+        assert!(lines.contains(&r#"ezboard_series_max{series="loss"} 2"#));
+        assert!(lines.contains(&r#"ezboard_series_points{series="loss"} 2"#));
+        assert!(lines.contains(&"ezboard_lines_read_total 4"));
+        assert!(lines.contains(&"ezboard_parse_failures_total 0"));
+        assert!(lines.contains(&"# TYPE ezboard_parse_failures_total counter"));
+    }
+
+    #[test]
+    fn run_labels() {
+        let mut app = App::new(5, 1.0);
+        app.state.runs = vec!["a".into(), "b".into()];
+        app.process_line_from(1, Default::default(), "loss 1.0");
+
+        let text = render(&app.state);
+        assert!(
+            text.contains(r#"ezboard_series_last{series="loss@b",metric="loss",run="b"} 1"#),
+            "{text}"
+        );
+
+        app.process_line_from(0, Default::default(), r#"{"top@5": 0.5}"#);
+        let text = render(&app.state);
+        assert!(
+            text.contains(r#"ezboard_series_last{series="top@5@a",metric="top@5",run="a"} 0.5"#),
+            "{text}"
+        );
+    }
+
+    #[test]
+    fn special_values() {
+        assert_eq!(format_value(f64::INFINITY), "+Inf");
+        assert_eq!(format_value(f64::NEG_INFINITY), "-Inf");
+        assert_eq!(format_value(f64::NAN), "NaN");
+        assert_eq!(format_value(-1.5), "-1.5");
+        assert_eq!(escape_label("a\\b\nc"), "a\\\\b\\nc");
+    }
+}
@@ -0,0 +1,124 @@
+/// Number of labels on a logarithmic y-axis
+const LOG_LABELS: usize = 5;
+
+/// Maps values onto the y-axis of a chart
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum YScale {
+    Linear,
+    /// Base 10 logarithm, only valid for strictly positive values
+    Log,
+    /// Symmetric logarithm `sign(v) * log10(1 + |v| / threshold)`
+    ///
+    /// Used instead of [`YScale::Log`] if the series contain zero or negative values.
+    SymLog {
+        threshold: f64,
+    },
+}
+
+impl YScale {
+    /// Picks a logarithmic scale suitable for the given values
+    pub fn log_for<'a>(values: impl Iterator<Item = &'a f64>) -> Self {
+        let mut min = f64::INFINITY;
+        let mut min_abs = f64::INFINITY;
+        for &val in values {
+            min = min.min(val);
+            if val != 0.0 {
+                min_abs = min_abs.min(val.abs());
+            }
+        }
+
+        if min > 0.0 {
+            YScale::Log
+        } else {
+            let threshold = if min_abs.is_finite() { min_abs } else { 1.0 };
+            YScale::SymLog { threshold }
+        }
+    }
+
+    pub fn apply(&self, val: f64) -> f64 {
+        match *self {
+            YScale::Linear => val,
+            YScale::Log => val.log10(),
+            YScale::SymLog { threshold } => val.signum() * (1.0 + val.abs() / threshold).log10(),
+        }
+    }
+
+    pub fn invert(&self, val: f64) -> f64 {
+        match *self {
+            YScale::Linear => val,
+            YScale::Log => 10f64.powf(val),
+            YScale::SymLog { threshold } => {
+                val.signum() * (10f64.powf(val.abs()) - 1.0) * threshold
+            }
+        }
+    }
+
+    /// Transforms the values of a series for plotting
+    pub fn apply_all(&self, data: &[(f64, f64)]) -> Vec<(f64, f64)> {
+        data.iter().map(|(t, val)| (*t, self.apply(*val))).collect()
+    }
+
+    /// Returns the original values at evenly spaced positions of the scaled axis
+    /// between `min` and `max`
+    pub fn ticks(&self, min: f64, max: f64) -> Vec<f64> {
+        match self {
+            YScale::Linear => vec![min, max],
+            _ => {
+                let (lo, hi) = (self.apply(min), self.apply(max));
+                (0..LOG_LABELS)
+                    .map(|i| {
+                        let pos = lo + (hi - lo) * i as f64 / (LOG_LABELS - 1) as f64;
+                        self.invert(pos)
+                    })
+                    .collect()
+            }
+        }
+    }
+
+    /// Short note for the axis title
+    pub fn note(&self) -> Option<&'static str> {
+        match self {
+            YScale::Linear => None,
+            YScale::Log => Some("log"),
+            YScale::SymLog { .. } => Some("symlog, values <= 0"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(a: f64, b: f64) {
+        assert!((a - b).abs() < 1e-9, "{a} != {b}");
+    }
+
+    #[test]
+    fn log() {
+        let scale = YScale::log_for([100.0, 1e-4].iter());
+        assert_eq!(scale, YScale::Log);
+        assert_close(scale.apply(100.0), 2.0);
+        assert_close(scale.apply(1e-4), -4.0);
+        assert_close(scale.invert(scale.apply(0.3)), 0.3);
+
+        let ticks = scale.ticks(1e-4, 1e4);
+        assert_eq!(ticks.len(), LOG_LABELS);
+        for (tick, expected) in ticks.into_iter().zip([1e-4, 1e-2, 1.0, 1e2, 1e4]) {
+            assert_close(tick, expected);
+        }
+    }
+
+    #[test]
+    fn symlog() {
+        let scale = YScale::log_for([-10.0, 0.0, 0.5, 100.0].iter());
+        assert_eq!(scale, YScale::SymLog { threshold: 0.5 });
+        assert_eq!(scale.apply(0.0), 0.0);
+        assert_close(scale.apply(-10.0), -scale.apply(10.0));
+        for val in [-10.0, 0.0, 0.5, 100.0] {
+            assert_close(scale.invert(scale.apply(val)), val);
+        }
+
+        let scale = YScale::log_for([0.0, 0.0].iter());
+        assert_eq!(scale, YScale::SymLog { threshold: 1.0 });
+    }
+}
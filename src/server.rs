@@ -0,0 +1,373 @@
+use std::{
+    net::SocketAddr,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use anyhow::{Context, Result};
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+    sync::watch,
+    task::JoinHandle,
+};
+
+use crate::{
+    app::AppState,
+    dashboard::{self, Changes, Snapshot},
+    prometheus,
+};
+
+/// Largest request head that is accepted
+const MAX_REQUEST_SIZE: usize = 8192;
+
+/// Time a client has to send its request
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Shortest time between two checks whether the published state has to be updated
+const PUBLISH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Time after which an idle event stream gets a comment, to notice closed connections
+const KEEPALIVE_INTERVAL: Duration = Duration::from_secs(15);
+
+/// State of the app as last published by the main loop
+#[derive(Default)]
+struct Published {
+    /// Prometheus exposition of all series
+    metrics: String,
+    dashboard: Arc<Snapshot>,
+}
+
+/// Minimal HTTP server for the web dashboard and the metrics published by the main loop
+///
+/// Serves the dashboard at `/`, its data at `/api/series` and as server-sent events at
+/// `/api/events`, and `/metrics` in the Prometheus text format. Requests are answered in
+/// their own tasks from the last published state, so they never wait for the TUI. The
+/// server stops when it's dropped.
+pub struct Server {
+    /// Task accepting connections
+    task: JoinHandle<()>,
+    /// Address the server is bound to
+    addr: SocketAddr,
+    published: Arc<watch::Sender<Published>>,
+    /// Time the state was last published
+    last_publish: Option<Instant>,
+    /// Task making the snapshot that is published next
+    pending: Option<JoinHandle<()>>,
+}
+
+impl Server {
+    pub async fn bind(addr: SocketAddr) -> Result<Self> {
+        let listener = TcpListener::bind(addr)
+            .await
+            .with_context(|| format!("Failed to serve on {addr}"))?;
+        let addr = listener.local_addr()?;
+        let (published, receiver) = watch::channel(Published::default());
+        let task = tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                tokio::spawn(handle(stream, receiver.clone()));
+            }
+        });
+        Ok(Self {
+            task,
+            addr,
+            published: Arc::new(published),
+            last_publish: None,
+            pending: None,
+        })
+    }
+
+    /// Address the server is bound to, with the actual port if port 0 was requested
+    pub fn local_addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// Makes the current values of `state` available to the next requests and sends them
+    /// to the dashboards, at most once per [`PUBLISH_INTERVAL`]
+    ///
+    /// Nothing is published if nothing changed. Only the new points are taken from
+    /// `state`, the snapshot of the dashboard is made on a blocking thread.
+    pub fn publish(&mut self, state: &AppState) {
+        if self
+            .last_publish
+            .is_some_and(|last| last.elapsed() < PUBLISH_INTERVAL)
+        {
+            return;
+        }
+        // The changes are taken relative to the last published snapshot
+        if self
+            .pending
+            .as_ref()
+            .is_some_and(|task| !task.is_finished())
+        {
+            return;
+        }
+        self.last_publish = Some(Instant::now());
+
+        let metrics = prometheus::render(state);
+        let (previous, same_metrics) = {
+            let published = self.published.borrow();
+            (published.dashboard.clone(), published.metrics == metrics)
+        };
+        let changes = Changes::new(state, &previous);
+        if changes.is_empty(&previous) {
+            if !same_metrics {
+                self.published
+                    .send_modify(|published| published.metrics = metrics);
+            }
+            return;
+        }
+
+        let published = self.published.clone();
+        self.pending = Some(tokio::task::spawn_blocking(move || {
+            let dashboard = Arc::new(changes.apply(&previous));
+            published.send_replace(Published { metrics, dashboard });
+        }));
+    }
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+async fn handle(mut stream: TcpStream, published: watch::Receiver<Published>) {
+    let request = tokio::time::timeout(REQUEST_TIMEOUT, read_request(&mut stream)).await;
+    let response = match request {
+        Ok(Some((method, path))) if method == "GET" && route(&path) == "/api/events" => {
+            return stream_events(stream, published).await;
+        }
+        Ok(Some((method, path))) => respond(&method, &path, &published),
+        Ok(None) => response("400 Bad Request", "text/plain", "Bad request\n"),
+        Err(_) => return,
+    };
+    let _ = stream.write_all(&response).await;
+    let _ = stream.shutdown().await;
+}
+
+/// Reads the head of a request and returns its method and path
+async fn read_request(stream: &mut (impl AsyncRead + Unpin)) -> Option<(String, String)> {
+    let mut head = Vec::new();
+    let mut buf = [0; 1024];
+    while !head.windows(4).any(|end| end == b"\r\n\r\n") {
+        if head.len() > MAX_REQUEST_SIZE {
+            return None;
+        }
+        let len = stream.read(&mut buf).await.ok()?;
+        if len == 0 {
+            break;
+        }
+        head.extend_from_slice(&buf[..len]);
+    }
+
+    let head = String::from_utf8_lossy(&head);
+    let mut request_line = head.lines().next()?.split_whitespace();
+    let method = request_line.next()?.to_owned();
+    let path = request_line.next()?.to_owned();
+    Some((method, path))
+}
+
+/// Sends an update of the dashboard whenever a new state is published
+///
+/// The first update contains all series, so reconnecting dashboards start over.
+async fn stream_events(mut stream: TcpStream, mut published: watch::Receiver<Published>) {
+    let head = "HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\n\
+                Cache-Control: no-cache\r\nConnection: close\r\n\r\n";
+    if stream.write_all(head.as_bytes()).await.is_err() {
+        return;
+    }
+    let mut sent: Option<Arc<Snapshot>> = None;
+    loop {
+        let snapshot = published.borrow_and_update().dashboard.clone();
+        // Nothing is sent if only the Prometheus metrics changed
+        if sent
+            .as_ref()
+            .is_none_or(|sent| !Arc::ptr_eq(sent, &snapshot))
+        {
+            let event = format!("data: {}\n\n", snapshot.update(sent.as_deref()));
+            if stream.write_all(event.as_bytes()).await.is_err() {
+                return;
+            }
+            sent = Some(snapshot);
+        }
+
+        loop {
+            tokio::select! {
+                changed = published.changed() => match changed {
+                    Ok(()) => break,
+                    Err(_) => return,
+                },
+                _ = tokio::time::sleep(KEEPALIVE_INTERVAL) => {
+                    if stream.write_all(b": keepalive\n\n").await.is_err() {
+                        return;
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Returns the path without the query
+fn route(path: &str) -> &str {
+    path.split('?').next().unwrap_or_default()
+}
+
+fn respond(method: &str, path: &str, published: &watch::Receiver<Published>) -> Vec<u8> {
+    if method != "GET" && method != "HEAD" {
+        return response(
+            "405 Method Not Allowed",
+            "text/plain",
+            "Method not allowed\n",
+        );
+    }
+    let mut response = match route(path) {
+        "/" => response("200 OK", "text/html; charset=utf-8", dashboard::PAGE),
+        "/api/series" => {
+            let dashboard = published.borrow().dashboard.clone();
+            let update = dashboard.update(None).to_string();
+            response("200 OK", "application/json", &update)
+        }
+        "/metrics" => response(
+            "200 OK",
+            "text/plain; version=0.0.4; charset=utf-8",
+            &published.borrow().metrics,
+        ),
+        _ => response("404 Not Found", "text/plain", "Not found\n"),
+    };
+    if method == "HEAD" {
+        let head_len = response
+            .windows(4)
+            .position(|end| end == b"\r\n\r\n")
+            .map_or(response.len(), |pos| pos + 4);
+        response.truncate(head_len);
+    }
+    response
+}
+
+fn response(status: &str, content_type: &str, body: &str) -> Vec<u8> {
+    format!(
+        "HTTP/1.1 {status}\r\nContent-Type: {content_type}\r\nContent-Length: {}\r\n\
+         Connection: close\r\n\r\n{body}",
+        body.len()
+    )
+    .into_bytes()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::App;
+
+    /// Publishes `state` right away and waits until it's published
+    async fn publish(server: &mut Server, state: &AppState) {
+        server.last_publish = None;
+        server.publish(state);
+        if let Some(task) = server.pending.take() {
+            task.await.unwrap();
+        }
+    }
+
+    async fn get(addr: SocketAddr, request: &str) -> String {
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        stream.write_all(request.as_bytes()).await.unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        response
+    }
+
+    #[tokio::test]
+    async fn metrics() {
+        let mut server = Server::bind("127.0.0.1:0".parse().unwrap()).await.unwrap();
+        let addr = server.local_addr();
+
+        let mut app = App::new(5, 1.0);
+        app.process_line("loss 0.5");
+        publish(&mut server, &app.state).await;
+
+        let response = get(addr, "GET /metrics HTTP/1.1\r\nHost: localhost\r\n\r\n").await;
+        let (head, body) = response.split_once("\r\n\r\n").unwrap();
+        assert!(head.starts_with("HTTP/1.1 200 OK\r\n"), "{head}");
+        assert!(head.contains(&format!("Content-Length: {}", body.len())));
+        assert!(body.contains("ezboard_series_last{series=\"loss\"} 0.5\n"));
+
+        // Without new points only the metrics are updated, no snapshot is made
+        let dashboard = server.published.borrow().dashboard.clone();
+        app.process_line("starting epoch");
+        server.last_publish = None;
+        server.publish(&app.state);
+        assert!(server.pending.is_none());
+        let published = server.published.borrow();
+        assert!(Arc::ptr_eq(&published.dashboard, &dashboard));
+        assert!(published.metrics.contains("ezboard_lines_read_total 2\n"));
+        drop(published);
+
+        let response = get(addr, "HEAD /metrics?x=1 HTTP/1.1\r\n\r\n").await;
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.ends_with("\r\n\r\n"));
+
+        let response = get(addr, "GET /other HTTP/1.1\r\n\r\n").await;
+        assert!(response.starts_with("HTTP/1.1 404 Not Found\r\n"));
+        let response = get(addr, "POST /metrics HTTP/1.1\r\n\r\n").await;
+        assert!(response.starts_with("HTTP/1.1 405 Method Not Allowed\r\n"));
+        let response = get(addr, "\r\n\r\n").await;
+        assert!(response.starts_with("HTTP/1.1 400 Bad Request\r\n"));
+    }
+
+    /// Reads from `stream` up to and including the next `delimiter`
+    async fn read_until(stream: &mut TcpStream, buf: &mut String, delimiter: &str) -> String {
+        while !buf.contains(delimiter) {
+            let mut chunk = [0; 1024];
+            let len = stream.read(&mut chunk).await.unwrap();
+            assert!(len > 0, "Stream closed");
+            buf.push_str(std::str::from_utf8(&chunk[..len]).unwrap());
+        }
+        let end = buf.find(delimiter).unwrap() + delimiter.len();
+        buf.drain(..end).collect()
+    }
+
+    #[tokio::test]
+    async fn dashboard() {
+        let mut server = Server::bind("127.0.0.1:0".parse().unwrap()).await.unwrap();
+        let addr = server.local_addr();
+        let mut app = App::new(5, 1.0);
+        app.process_line("Step 1 loss 0.5");
+        publish(&mut server, &app.state).await;
+
+        let response = get(addr, "GET / HTTP/1.1\r\n\r\n").await;
+        assert!(response.contains("Content-Type: text/html"));
+        assert!(response.contains("api/events"));
+        let response = get(addr, "GET /api/series HTTP/1.1\r\n\r\n").await;
+        let (_, body) = response.split_once("\r\n\r\n").unwrap();
+        let series: serde_json::Value = serde_json::from_str(body).unwrap();
+        assert_eq!(series["series"]["loss"]["values"][0], 0.5);
+
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        stream
+            .write_all(b"GET /api/events HTTP/1.1\r\n\r\n")
+            .await
+            .unwrap();
+        let mut buf = String::new();
+        let head = read_until(&mut stream, &mut buf, "\r\n\r\n").await;
+        assert!(head.contains("Content-Type: text/event-stream"), "{head}");
+        let event = read_until(&mut stream, &mut buf, "\n\n").await;
+        assert!(event.starts_with("data: {\"reset\":true,"), "{event}");
+
+        // Lines without metrics don't cause an event, only the new point is sent
+        app.process_line("starting epoch");
+        publish(&mut server, &app.state).await;
+        // Lets the stream see the change before the next one
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        app.process_line("Step 2 loss 0.25");
+        publish(&mut server, &app.state).await;
+        let event = read_until(&mut stream, &mut buf, "\n\n").await;
+        let update: serde_json::Value =
+            serde_json::from_str(event.strip_prefix("data: ").unwrap()).unwrap();
+        assert_eq!(update["reset"], false);
+        assert_eq!(update["series"]["loss"]["start"], 1);
+        assert_eq!(
+            update["series"]["loss"]["values"],
+            serde_json::json!([0.25])
+        );
+    }
+}
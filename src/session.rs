@@ -0,0 +1,377 @@
+use std::{
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{bail, Context, Result};
+use clap::ValueEnum;
+use serde_json::{json, Map, Value};
+use tokio::task::JoinHandle;
+
+use crate::{
+    app::{AppState, Timeseries},
+    input::LineSource,
+    smoothing::SmoothingMode,
+    ui::UiState,
+    view::StepView,
+};
+
+/// Version of the session file format, increased on incompatible changes
+const SESSION_VERSION: u64 = 1;
+
+/// Interval in which the session is checkpointed if not given otherwise
+pub const DEFAULT_CHECKPOINT_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Writes the series, line buffer and view settings of `state` to the session file at `path`
+///
+/// The file is replaced atomically, so a crash while saving keeps the previous checkpoint.
+pub fn save(state: &AppState, path: &Path) -> Result<()> {
+    write(&to_json(state), path)
+}
+
+/// Like [`save`], but serializes and writes the session on a blocking thread
+///
+/// Only the copy of the values is made on the calling thread, so checkpoints don't stall
+/// the UI.
+pub fn save_in_background(state: &AppState, path: &Path) -> JoinHandle<Result<()>> {
+    let session = to_json(state);
+    let path = path.to_owned();
+    tokio::task::spawn_blocking(move || write(&session, &path))
+}
+
+/// Writes `session` next to `path` and moves it there
+fn write(session: &Value, path: &Path) -> Result<()> {
+    let mut tmp_path = path.as_os_str().to_owned();
+    tmp_path.push(".tmp");
+    let tmp_path = PathBuf::from(tmp_path);
+    let content = serde_json::to_string(session)?;
+    std::fs::write(&tmp_path, content)
+        .with_context(|| format!("Failed to write {}", tmp_path.display()))?;
+    std::fs::rename(&tmp_path, path)
+        .with_context(|| format!("Failed to write {}", path.display()))?;
+    Ok(())
+}
+
+/// Restores a session saved with [`save`] into `state`
+///
+/// Values the input repeats up to the last restored step of a series are skipped later on.
+pub fn restore(state: &mut AppState, path: &Path) -> Result<()> {
+    let content = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+    let session: Value = serde_json::from_str(&content)
+        .with_context(|| format!("{} isn't a session file", path.display()))?;
+    from_json(state, &session).with_context(|| format!("Invalid session file {}", path.display()))
+}
+
+fn to_json(state: &AppState) -> Value {
+    let series: Map<String, Value> = state
+        .data
+        .iter()
+        .map(|(key, data)| {
+            let points: Vec<Value> = data
+                .get_data()
+                .iter()
+                .zip(data.get_timestamps())
+                .map(|((t, val), time)| json!([number(*t), number(*val), millis(*time)]))
+                .collect();
+            (key.clone(), Value::Array(points))
+        })
+        .collect();
+    let lines: Vec<Value> = state
+        .linebuf
+        .iter()
+        .map(|(source, line)| {
+            let source = match source {
+                LineSource::Stdout => "stdout",
+                LineSource::Stderr => "stderr",
+            };
+            json!([source, line])
+        })
+        .collect();
+    let step_view = match state.step_view {
+        StepView::Full => json!({ "mode": "full" }),
+        StepView::Window { start, end } => json!({ "mode": "window", "start": start, "end": end }),
+        StepView::Tail { width } => json!({ "mode": "tail", "width": width }),
+    };
+    let view = match state.ui_state {
+        UiState::Plot => "plot",
+        UiState::KeySelection => "key_selection",
+        UiState::Passthrough => "passthrough",
+        UiState::Grid => "grid",
+    };
+    let smoothing = &state.smoothing;
+
+    json!({
+        "version": SESSION_VERSION,
+        "series": series,
+        "lines": lines,
+        "display_keys": state.display_keys,
+        "marked_keys": state.marked_keys,
+        "runs": state.runs,
+        "log_scale": state.log_scale,
+        "log_scale_overrides": state.log_scale_overrides,
+        "smoothing": {
+            "mode": smoothing.mode.to_possible_value().map(|mode| mode.get_name().to_owned()),
+            "ema_factor": smoothing.ema_factor,
+            "window": smoothing.window,
+        },
+        "step_view": step_view,
+        "view": view,
+    })
+}
+
+fn from_json(state: &mut AppState, session: &Value) -> Result<()> {
+    match session["version"].as_u64() {
+        Some(SESSION_VERSION) => {}
+        Some(version) => bail!("Unsupported session version {version}"),
+        None => bail!("Missing session version"),
+    }
+
+    let series = session["series"].as_object().context("Missing series")?;
+    for (key, points) in series {
+        let mut data = Timeseries::default();
+        for point in points.as_array().context("Invalid series")? {
+            let (Some(t), Some(val), Some(time)) = (
+                from_number(&point[0]),
+                from_number(&point[1]),
+                point[2].as_u64(),
+            ) else {
+                bail!("Invalid data point {point} of {key}");
+            };
+            data.push(t, val, UNIX_EPOCH + Duration::from_millis(time));
+        }
+        if let Some((t, _)) = data.get_data().last() {
+            state.resumed_steps.insert(key.clone(), *t);
+        }
+        state.data.insert(key.clone(), data);
+    }
+
+    for line in session["lines"].as_array().into_iter().flatten() {
+        let source = match line[0].as_str() {
+            Some("stderr") => LineSource::Stderr,
+            _ => LineSource::Stdout,
+        };
+        let line = line[1].as_str().context("Invalid line")?;
+        state.linebuf.add((source, line.to_owned()));
+    }
+
+    let strings = |value: &Value| -> Vec<String> {
+        let values = value.as_array().into_iter().flatten();
+        values
+            .filter_map(|s| Some(s.as_str()?.to_owned()))
+            .collect()
+    };
+    state.display_keys = strings(&session["display_keys"]);
+    state.marked_keys = strings(&session["marked_keys"]).into_iter().collect();
+    if state.runs.is_empty() {
+        state.runs = strings(&session["runs"]);
+    }
+
+    state.log_scale = session["log_scale"].as_bool().unwrap_or(state.log_scale);
+    let overrides = session["log_scale_overrides"]
+        .as_object()
+        .into_iter()
+        .flatten();
+    state.log_scale_overrides = overrides
+        .filter_map(|(key, log_scale)| Some((key.clone(), log_scale.as_bool()?)))
+        .collect();
+
+    let smoothing = &session["smoothing"];
+    if let Some(mode) = smoothing["mode"].as_str() {
+        state.smoothing.mode = SmoothingMode::from_str(mode, true).map_err(anyhow::Error::msg)?;
+    }
+    if let Some(ema_factor) = smoothing["ema_factor"].as_f64() {
+        state.smoothing.ema_factor = ema_factor.clamp(0.0, 1.0);
+    }
+    if let Some(window) = smoothing["window"].as_u64() {
+        state.smoothing.window = window.max(1) as usize;
+    }
+
+    let step_view = &session["step_view"];
+    state.step_view = match (step_view["mode"].as_str(), step_view) {
+        (Some("window"), view) => StepView::Window {
+            start: view["start"].as_f64().context("Invalid step view")?,
+            end: view["end"].as_f64().context("Invalid step view")?,
+        },
+        (Some("tail"), view) => StepView::Tail {
+            width: view["width"].as_f64().context("Invalid step view")?,
+        },
+        _ => StepView::Full,
+    };
+    state.ui_state = match session["view"].as_str() {
+        Some("grid") => UiState::Grid,
+        Some("passthrough") => UiState::Passthrough,
+        _ => UiState::Plot,
+    };
+    Ok(())
+}
+
+/// Returns `value` as JSON number, or as string if it's NaN or infinite, which JSON
+/// numbers can't be
+fn number(value: f64) -> Value {
+    match value {
+        value if value.is_nan() => json!("NaN"),
+        f64::INFINITY => json!("inf"),
+        f64::NEG_INFINITY => json!("-inf"),
+        value => json!(value),
+    }
+}
+
+/// Reads a value written by [`number`]
+fn from_number(value: &Value) -> Option<f64> {
+    match value.as_str() {
+        Some("NaN") => Some(f64::NAN),
+        Some("inf") => Some(f64::INFINITY),
+        Some("-inf") => Some(f64::NEG_INFINITY),
+        _ => value.as_f64(),
+    }
+}
+
+/// Milliseconds since the unix epoch
+fn millis(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_millis() as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::App;
+
+    fn temp_path(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("ezboard-{}-{name}", std::process::id()))
+    }
+
+    #[test]
+    fn save_and_restore() {
+        let path = temp_path("session.json");
+        let mut app = App::new(5, 0.5);
+        app.process_line("Step 1 loss 2.0");
+        app.process_line_from(0, LineSource::Stderr, "Step 2 loss 1.0 acc 0.5");
+        app.state.display_keys = vec!["acc".into(), "loss".into()];
+        app.state.log_scale_overrides.insert("acc".into(), true);
+        app.state.smoothing.mode = SmoothingMode::Gaussian;
+        app.state.step_view = StepView::Tail { width: 1.0 };
+        app.state.ui_state = UiState::Grid;
+        save(&app.state, &path).unwrap();
+
+        let mut resumed = App::new(5, 1.0);
+        restore(&mut resumed.state, &path).unwrap();
+        let state = &resumed.state;
+        assert_eq!(state.sorted_keys(), ["acc", "loss"]);
+        assert_eq!(
+            state.data["loss"].get_data(),
+            app.state.data["loss"].get_data()
+        );
+        assert_eq!(state.data["loss"].get_min(), 1.0);
+        assert_eq!(
+            millis(state.data["loss"].get_timestamps()[0]),
+            millis(app.state.data["loss"].get_timestamps()[0])
+        );
+        let lines: Vec<_> = state.linebuf.iter().cloned().collect();
+        let expected: Vec<_> = app.state.linebuf.iter().cloned().collect();
+        assert_eq!(lines, expected);
+        assert_eq!(state.display_keys, ["acc", "loss"]);
+        assert!(state.is_log_scale("acc"));
+        assert_eq!(state.smoothing.mode, SmoothingMode::Gaussian);
+        assert_eq!(state.smoothing.ema_factor, 0.5);
+        assert_eq!(state.step_view, StepView::Tail { width: 1.0 });
+        assert!(state.ui_state == UiState::Grid);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn save_in_background() {
+        let path = temp_path("background.session");
+        let mut app = App::new(5, 1.0);
+        app.process_line("Step 1 loss 3.0");
+        let task = super::save_in_background(&app.state, &path);
+        app.process_line("Step 2 loss 2.0");
+        task.await.unwrap().unwrap();
+
+        let mut resumed = App::new(5, 1.0);
+        restore(&mut resumed.state, &path).unwrap();
+        assert_eq!(resumed.state.data["loss"].get_data(), &vec![(1.0, 3.0)]);
+        assert!(!temp_path("background.session.tmp").exists());
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn skip_resumed_steps() {
+        let path = temp_path("resume.json");
+        let mut app = App::new(5, 1.0);
+        app.process_line("Step 1 loss 3.0");
+        app.process_line("Step 2 loss 2.0");
+        save(&app.state, &path).unwrap();
+
+        let mut resumed = App::new(5, 1.0);
+        restore(&mut resumed.state, &path).unwrap();
+        for line in ["Step 1 loss 3.0", "Step 2 loss 2.0", "Step 3 loss 1.0"] {
+            resumed.process_line(line);
+        }
+        // Restored steps stay skipped after newer ones were read
+        resumed.process_line("Step 2 loss 5.0");
+        resumed.process_line("Step 4 loss 0.5");
+        assert_eq!(
+            resumed.state.data["loss"].get_data(),
+            &vec![(1.0, 3.0), (2.0, 2.0), (3.0, 1.0), (4.0, 0.5)]
+        );
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn non_finite_values() {
+        let path = temp_path("non-finite.json");
+        // Values like these come from event files or custom patterns
+        let mut app = App::new(5, 1.0);
+        app.process_line("Step 1 loss 1.0");
+        let data = app.state.data.get_mut("loss").unwrap();
+        for (t, val) in [
+            (2.0, f64::INFINITY),
+            (3.0, f64::NEG_INFINITY),
+            (4.0, f64::NAN),
+        ] {
+            data.push(t, val, SystemTime::now());
+        }
+        save(&app.state, &path).unwrap();
+
+        let mut resumed = App::new(5, 1.0);
+        restore(&mut resumed.state, &path).unwrap();
+        let values: Vec<_> = resumed.state.data["loss"]
+            .get_data()
+            .iter()
+            .map(|(t, val)| (*t, val.to_string()))
+            .collect();
+        let expected: Vec<_> = app.state.data["loss"]
+            .get_data()
+            .iter()
+            .map(|(t, val)| (*t, val.to_string()))
+            .collect();
+        assert_eq!(values, expected);
+        assert_eq!(values.last().unwrap().1, "NaN");
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn invalid_sessions() {
+        let path = temp_path("invalid.json");
+        let mut state = AppState::default();
+
+        std::fs::write(&path, "loss 1").unwrap();
+        assert!(restore(&mut state, &path).is_err());
+        std::fs::write(&path, r#"{"version": 2, "series": {}}"#).unwrap();
+        assert!(restore(&mut state, &path).is_err());
+        std::fs::write(
+            &path,
+            r#"{"version": 1, "series": {"loss": [[1, "a", 0]]}}"#,
+        )
+        .unwrap();
+        assert!(restore(&mut state, &path).is_err());
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,265 @@
+use clap::ValueEnum;
+
+/// Algorithm used to smooth a series for display
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum SmoothingMode {
+    /// Exponential moving average seeded with the first value
+    #[default]
+    Ema,
+    /// Exponential moving average with bias correction as in TensorBoard
+    DebiasedEma,
+    /// Mean of the last `window` values
+    TrailingMean,
+    /// Mean of the `window` values around each value
+    CenteredMean,
+    /// Median of the last `window` values
+    MovingMedian,
+    /// Gaussian kernel with a standard deviation of `window / 2` values
+    Gaussian,
+}
+
+impl SmoothingMode {
+    /// Returns the next mode, wrapping around after the last one
+    pub fn next(self) -> Self {
+        let modes = Self::value_variants();
+        let idx = modes.iter().position(|mode| *mode == self).unwrap_or(0);
+        modes[(idx + 1) % modes.len()]
+    }
+
+    /// Whether the mode is parametrized by the EMA factor instead of the window size
+    pub fn uses_ema_factor(self) -> bool {
+        matches!(self, SmoothingMode::Ema | SmoothingMode::DebiasedEma)
+    }
+}
+
+/// Smoothing algorithm together with its parameters
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Smoothing {
+    pub mode: SmoothingMode,
+    /// Weight of the newest value for the EMA modes, 1 disables smoothing
+    pub ema_factor: f64,
+    /// Number of values for the window based modes, 1 disables smoothing
+    pub window: usize,
+}
+
+impl Default for Smoothing {
+    fn default() -> Self {
+        Self {
+            mode: SmoothingMode::default(),
+            ema_factor: 1.0,
+            window: 10,
+        }
+    }
+}
+
+impl Smoothing {
+    /// Whether the smoothed values can differ from the raw values
+    pub fn is_active(&self) -> bool {
+        if self.mode.uses_ema_factor() {
+            self.ema_factor < 1.0
+        } else {
+            self.window > 1
+        }
+    }
+
+    /// Short description of the mode and its parameter
+    pub fn label(&self) -> String {
+        match self.mode {
+            SmoothingMode::Ema => format!("EMA factor {:.2}", self.ema_factor),
+            SmoothingMode::DebiasedEma => format!("debiased EMA factor {:.2}", self.ema_factor),
+            SmoothingMode::TrailingMean => format!("trailing mean of {}", self.window),
+            SmoothingMode::CenteredMean => format!("centered mean of {}", self.window),
+            SmoothingMode::MovingMedian => format!("moving median of {}", self.window),
+            SmoothingMode::Gaussian => format!("gaussian sigma {}", self.window as f64 / 2.0),
+        }
+    }
+
+    pub fn apply(&self, values: &[f64]) -> Vec<f64> {
+        if !self.is_active() {
+            return values.to_vec();
+        }
+
+        let window = self.window;
+        match self.mode {
+            SmoothingMode::Ema => ema(values, self.ema_factor),
+            SmoothingMode::DebiasedEma => debiased_ema(values, self.ema_factor),
+            SmoothingMode::TrailingMean => (0..values.len())
+                .map(|i| mean(&values[(i + 1).saturating_sub(window)..=i]))
+                .collect(),
+            SmoothingMode::CenteredMean => {
+                let radius = window / 2;
+                (0..values.len())
+                    .map(|i| {
+                        let end = usize::min(i + radius + 1, values.len());
+                        mean(&values[i.saturating_sub(radius)..end])
+                    })
+                    .collect()
+            }
+            SmoothingMode::MovingMedian => (0..values.len())
+                .map(|i| median(&values[(i + 1).saturating_sub(window)..=i]))
+                .collect(),
+            SmoothingMode::Gaussian => gaussian(values, window as f64 / 2.0),
+        }
+    }
+}
+
+fn ema(values: &[f64], factor: f64) -> Vec<f64> {
+    let mut smoothed: Vec<f64> = Vec::with_capacity(values.len());
+    for val in values {
+        let val = match smoothed.last() {
+            Some(last) => factor * val + (1.0 - factor) * last,
+            None => *val,
+        };
+        smoothed.push(val);
+    }
+    smoothed
+}
+
+fn debiased_ema(values: &[f64], factor: f64) -> Vec<f64> {
+    let weight = 1.0 - factor;
+    if weight >= 1.0 {
+        // Bias correction is undefined for a constant average, fall back to the seeded EMA
+        return ema(values, factor);
+    }
+
+    let mut last = 0.0;
+    values
+        .iter()
+        .enumerate()
+        .map(|(i, val)| {
+            last = last * weight + factor * val;
+            last / (1.0 - weight.powi(i as i32 + 1))
+        })
+        .collect()
+}
+
+fn mean(values: &[f64]) -> f64 {
+    values.iter().sum::<f64>() / values.len() as f64
+}
+
+fn median(values: &[f64]) -> f64 {
+    let mut sorted = values.to_vec();
+    sorted.sort_by(f64::total_cmp);
+    let mid = sorted.len() / 2;
+    if sorted.len() % 2 == 0 {
+        (sorted[mid - 1] + sorted[mid]) / 2.0
+    } else {
+        sorted[mid]
+    }
+}
+
+fn gaussian(values: &[f64], sigma: f64) -> Vec<f64> {
+    let radius = (3.0 * sigma).ceil() as usize;
+    let kernel: Vec<f64> = (0..=radius)
+        .map(|d| (-((d * d) as f64) / (2.0 * sigma * sigma)).exp())
+        .collect();
+
+    (0..values.len())
+        .map(|i| {
+            let end = usize::min(i + radius + 1, values.len());
+            let (sum, norm) = (i.saturating_sub(radius)..end).fold((0.0, 0.0), |(sum, norm), j| {
+                let k = kernel[i.abs_diff(j)];
+                (sum + k * values[j], norm + k)
+            });
+            sum / norm
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const VALUES: [f64; 5] = [1.0, 3.0, 2.0, 6.0, 4.0];
+
+    fn assert_close(actual: Vec<f64>, expected: &[f64]) {
+        assert_eq!(actual.len(), expected.len());
+        for (a, e) in actual.iter().zip(expected) {
+            assert!((a - e).abs() < 1e-9, "{actual:?} != {expected:?}");
+        }
+    }
+
+    fn smoothing(mode: SmoothingMode, ema_factor: f64, window: usize) -> Smoothing {
+        Smoothing {
+            mode,
+            ema_factor,
+            window,
+        }
+    }
+
+    #[test]
+    fn ema() {
+        let s = smoothing(SmoothingMode::Ema, 0.5, 1);
+        assert_close(s.apply(&VALUES), &[1.0, 2.0, 2.0, 4.0, 4.0]);
+
+        let s = smoothing(SmoothingMode::Ema, 1.0, 1);
+        assert_close(s.apply(&VALUES), &VALUES);
+        assert!(!s.is_active());
+    }
+
+    #[test]
+    fn debiased_ema() {
+        let s = smoothing(SmoothingMode::DebiasedEma, 0.5, 1);
+        assert_close(
+            s.apply(&VALUES),
+            &[1.0, 7.0 / 3.0, 15.0 / 7.0, 4.2, 127.0 / 31.0],
+        );
+
+        let s = smoothing(SmoothingMode::DebiasedEma, 0.0, 1);
+        assert_close(s.apply(&VALUES), &[1.0; 5]);
+    }
+
+    #[test]
+    fn trailing_mean() {
+        let s = smoothing(SmoothingMode::TrailingMean, 1.0, 2);
+        assert_close(s.apply(&VALUES), &[1.0, 2.0, 2.5, 4.0, 5.0]);
+    }
+
+    #[test]
+    fn centered_mean() {
+        let s = smoothing(SmoothingMode::CenteredMean, 1.0, 3);
+        assert_close(s.apply(&VALUES), &[2.0, 2.0, 11.0 / 3.0, 4.0, 5.0]);
+    }
+
+    #[test]
+    fn moving_median() {
+        let s = smoothing(SmoothingMode::MovingMedian, 1.0, 3);
+        assert_close(s.apply(&VALUES), &[1.0, 2.0, 2.0, 3.0, 4.0]);
+    }
+
+    #[test]
+    fn gaussian() {
+        // A window of 2 is a sigma of 1, the kernel e^(-d²/2) is cut off at a distance of 3
+        // and normalized over the values inside the series
+        let s = smoothing(SmoothingMode::Gaussian, 1.0, 2);
+        let k = |d: f64| (-d * d / 2.0).exp();
+        let full = k(0.0) + 2.0 * (k(1.0) + k(2.0) + k(3.0));
+        let smoothed = s.apply(&[0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0]);
+        assert_close(
+            smoothed,
+            &[
+                k(3.0) / (k(0.0) + k(1.0) + k(2.0) + k(3.0)),
+                k(2.0) / (k(0.0) + 2.0 * k(1.0) + k(2.0) + k(3.0)),
+                k(1.0) / (k(0.0) + 2.0 * (k(1.0) + k(2.0)) + k(3.0)),
+                k(0.0) / full,
+                k(1.0) / (k(0.0) + 2.0 * (k(1.0) + k(2.0)) + k(3.0)),
+                k(2.0) / (k(0.0) + 2.0 * k(1.0) + k(2.0) + k(3.0)),
+                k(3.0) / (k(0.0) + k(1.0) + k(2.0) + k(3.0)),
+            ],
+        );
+
+        // The symmetric kernel keeps a line away from the borders
+        let line: Vec<f64> = (0..11).map(f64::from).collect();
+        assert!((s.apply(&line)[5] - 5.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn cycle_modes() {
+        let mut mode = SmoothingMode::Ema;
+        for _ in 0..SmoothingMode::value_variants().len() {
+            mode = mode.next();
+        }
+        assert_eq!(mode, SmoothingMode::Ema);
+        assert_eq!(SmoothingMode::Ema.next(), SmoothingMode::DebiasedEma);
+    }
+}
@@ -0,0 +1,336 @@
+use std::path::Path;
+
+use anyhow::{bail, Context, Result};
+use ratatui::{
+    backend::TestBackend,
+    buffer::{Buffer, Cell},
+    style::{Color, Modifier},
+    Terminal,
+};
+
+use crate::{app::App, ui};
+
+/// File written by the snapshot hotkey if `--snapshot` isn't given
+pub const DEFAULT_SNAPSHOT_PATH: &str = "ezboard-snapshot.txt";
+
+/// Size of snapshots in terminal cells if `--snapshot-size` isn't given
+pub const DEFAULT_SNAPSHOT_SIZE: (u16, u16) = (120, 30);
+
+/// File format of a snapshot
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SnapshotFormat {
+    /// Plain text without colors
+    Text,
+    /// Text with ANSI color escape codes, e.g. for `cat` in a terminal
+    Ansi,
+    /// Vector image with one text element per line
+    Svg,
+}
+
+impl SnapshotFormat {
+    /// Chooses the format by the extension of `path`
+    pub fn from_path(path: &Path) -> Result<Self> {
+        let extension = path
+            .extension()
+            .map(|ext| ext.to_string_lossy().to_lowercase());
+        match extension.as_deref() {
+            Some("txt") => Ok(SnapshotFormat::Text),
+            Some("ans" | "ansi") => Ok(SnapshotFormat::Ansi),
+            Some("svg") => Ok(SnapshotFormat::Svg),
+            _ => bail!(
+                "Can't write a snapshot to {}, use a .txt, .ans or .svg file",
+                path.display()
+            ),
+        }
+    }
+}
+
+/// Renders the current view of `app` into a buffer of `width` x `height` cells
+///
+/// Drawing stores the layout of the view in `app`, that of the terminal is restored afterwards
+/// so paging and scrolling keep working on the terminal's size.
+pub fn render(app: &mut App, (width, height): (u16, u16)) -> Result<Buffer> {
+    let state = &app.state;
+    let layout = (
+        state.grid_columns,
+        state.grid_page_size,
+        state.log_view.height,
+        state.selection_list_state.clone(),
+    );
+    let mut terminal = Terminal::new(TestBackend::new(width, height))?;
+    let drawn = terminal.draw(|frame| ui::render(app, frame)).map(|_| ());
+    let state = &mut app.state;
+    (
+        state.grid_columns,
+        state.grid_page_size,
+        state.log_view.height,
+        state.selection_list_state,
+    ) = layout;
+    drawn?;
+    Ok(terminal.backend().buffer().clone())
+}
+
+/// Writes the current view of `app` to `path`, in the format given by its extension
+pub fn write(app: &mut App, path: &Path, size: (u16, u16)) -> Result<()> {
+    let format = SnapshotFormat::from_path(path)?;
+    let buffer = render(app, size)?;
+    let content = match format {
+        SnapshotFormat::Text => to_text(&buffer),
+        SnapshotFormat::Ansi => to_ansi(&buffer),
+        SnapshotFormat::Svg => to_svg(&buffer),
+    };
+    std::fs::write(path, content).with_context(|| format!("Failed to write {}", path.display()))
+}
+
+/// Returns the cells of each line of `buffer`
+fn lines(buffer: &Buffer) -> impl Iterator<Item = &[Cell]> {
+    buffer.content.chunks(buffer.area.width.max(1) as usize)
+}
+
+/// Returns the foreground and background color of `cell`, with reversed colors swapped
+fn colors(cell: &Cell) -> (Color, Color) {
+    if cell.modifier.contains(Modifier::REVERSED) {
+        let fg = if cell.bg == Color::Reset {
+            Color::Black
+        } else {
+            cell.bg
+        };
+        let bg = if cell.fg == Color::Reset {
+            Color::White
+        } else {
+            cell.fg
+        };
+        (fg, bg)
+    } else {
+        (cell.fg, cell.bg)
+    }
+}
+
+fn to_text(buffer: &Buffer) -> String {
+    let mut text = String::new();
+    for line in lines(buffer) {
+        let line: String = line.iter().map(Cell::symbol).collect();
+        text += line.trim_end();
+        text.push('\n');
+    }
+    text
+}
+
+fn to_ansi(buffer: &Buffer) -> String {
+    let mut ansi = String::new();
+    for line in lines(buffer) {
+        let mut style = None;
+        for cell in line {
+            let (fg, bg) = colors(cell);
+            let bold = cell.modifier.contains(Modifier::BOLD);
+            if style != Some((fg, bg, bold)) {
+                let mut codes = vec!["0".to_owned(), ansi_color(fg, false), ansi_color(bg, true)];
+                if bold {
+                    codes.push("1".to_owned());
+                }
+                ansi += &format!("\x1b[{}m", codes.join(";"));
+                style = Some((fg, bg, bold));
+            }
+            ansi += cell.symbol();
+        }
+        ansi += "\x1b[0m\n";
+    }
+    ansi
+}
+
+/// Returns the SGR parameters selecting `color` as foreground or background color
+fn ansi_color(color: Color, background: bool) -> String {
+    let offset = if background { 10 } else { 0 };
+    let code = match color {
+        Color::Reset => 39,
+        Color::Black => 30,
+        Color::Red => 31,
+        Color::Green => 32,
+        Color::Yellow => 33,
+        Color::Blue => 34,
+        Color::Magenta => 35,
+        Color::Cyan => 36,
+        Color::Gray => 37,
+        Color::DarkGray => 90,
+        Color::LightRed => 91,
+        Color::LightGreen => 92,
+        Color::LightYellow => 93,
+        Color::LightBlue => 94,
+        Color::LightMagenta => 95,
+        Color::LightCyan => 96,
+        Color::White => 97,
+        Color::Rgb(r, g, b) => return format!("{};2;{r};{g};{b}", 38 + offset),
+        Color::Indexed(idx) => return format!("{};5;{idx}", 38 + offset),
+    };
+    (code + offset).to_string()
+}
+
+/// Width and height of a cell in the SVG, for a monospace font of size 14
+const SVG_CELL: (f64, f64) = (8.4, 17.0);
+
+fn to_svg(buffer: &Buffer) -> String {
+    let (cell_width, cell_height) = SVG_CELL;
+    let width = buffer.area.width as f64 * cell_width;
+    let height = buffer.area.height as f64 * cell_height;
+    let mut svg = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{width}\" height=\"{height}\" \
+         font-family=\"monospace\" font-size=\"14\" xml:space=\"preserve\">\n\
+         <rect width=\"100%\" height=\"100%\" fill=\"{}\"/>\n",
+        svg_color(Color::Black)
+    );
+
+    for (y, line) in lines(buffer).enumerate() {
+        let top = y as f64 * cell_height;
+        for (x, cell) in line.iter().enumerate() {
+            let (_, bg) = colors(cell);
+            if bg != Color::Reset {
+                svg += &format!(
+                    "<rect x=\"{:.1}\" y=\"{top:.1}\" width=\"{cell_width}\" height=\"{cell_height}\" fill=\"{}\"/>\n",
+                    x as f64 * cell_width,
+                    svg_color(bg)
+                );
+            }
+        }
+
+        // Cells are placed one by one, so wide glyphs like braille can't shift the columns
+        let baseline = top + cell_height * 0.8;
+        svg += &format!("<text y=\"{baseline:.1}\">");
+        for (x, cell) in line.iter().enumerate() {
+            if cell.symbol().trim().is_empty() {
+                continue;
+            }
+            let (fg, _) = colors(cell);
+            let fg = if fg == Color::Reset { Color::White } else { fg };
+            svg += &format!(
+                "<tspan x=\"{:.1}\" fill=\"{}\">{}</tspan>",
+                x as f64 * cell_width,
+                svg_color(fg),
+                xml_escape(cell.symbol())
+            );
+        }
+        svg += "</text>\n";
+    }
+    svg += "</svg>\n";
+    svg
+}
+
+/// Returns `color` as hex color, named colors use the xterm palette
+fn svg_color(color: Color) -> String {
+    let (r, g, b) = match color {
+        Color::Reset | Color::Black => (0, 0, 0),
+        Color::Red => (205, 0, 0),
+        Color::Green => (0, 205, 0),
+        Color::Yellow => (205, 205, 0),
+        Color::Blue => (0, 0, 238),
+        Color::Magenta => (205, 0, 205),
+        Color::Cyan => (0, 205, 205),
+        Color::Gray => (229, 229, 229),
+        Color::DarkGray => (127, 127, 127),
+        Color::LightRed => (255, 0, 0),
+        Color::LightGreen => (0, 255, 0),
+        Color::LightYellow => (255, 255, 0),
+        Color::LightBlue => (92, 92, 255),
+        Color::LightMagenta => (255, 0, 255),
+        Color::LightCyan => (0, 255, 255),
+        Color::White | Color::Indexed(_) => (255, 255, 255),
+        Color::Rgb(r, g, b) => (r, g, b),
+    };
+    format!("#{r:02x}{g:02x}{b:02x}")
+}
+
+fn xml_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn app() -> App {
+        let mut app = App::new(5, 1.0);
+        for step in 0..20 {
+            app.process_line(&format!("Step {step} loss {}", 1.0 / (step as f64 + 1.0)));
+        }
+        app
+    }
+
+    #[test]
+    fn format_by_extension() {
+        assert_eq!(
+            SnapshotFormat::from_path(Path::new("loss.TXT")).unwrap(),
+            SnapshotFormat::Text
+        );
+        assert_eq!(
+            SnapshotFormat::from_path(Path::new("loss.ans")).unwrap(),
+            SnapshotFormat::Ansi
+        );
+        assert_eq!(
+            SnapshotFormat::from_path(Path::new("loss.svg")).unwrap(),
+            SnapshotFormat::Svg
+        );
+        assert!(SnapshotFormat::from_path(Path::new("loss.png")).is_err());
+    }
+
+    #[test]
+    fn text() {
+        let buffer = render(&mut app(), (40, 10)).unwrap();
+        let text = to_text(&buffer);
+        assert_eq!(text.lines().count(), 10);
+        assert!(text.contains("loss"), "{text}");
+        assert!(text.lines().all(|line| !line.ends_with(' ')));
+    }
+
+    #[test]
+    fn stats_panel() {
+        let mut app = app();
+        app.handle_keypress(crossterm::event::KeyCode::Char('i'));
+        let text = to_text(&render(&mut app, (80, 12)).unwrap());
+        assert!(text.contains("Stats (window 10)"), "{text}");
+        assert!(text.contains("last    0.0500 @ 19"), "{text}");
+        assert!(text.contains("best    0.0500 @ 19"), "{text}");
+        assert!(text.contains("points  20"), "{text}");
+    }
+
+    #[test]
+    fn keeps_layout() {
+        let mut app = app();
+        let mut terminal = Terminal::new(TestBackend::new(80, 24)).unwrap();
+        // The grid sets its columns and page size, the passthrough view its height
+        for key in ['g', 'p'] {
+            app.handle_keypress(crossterm::event::KeyCode::Char(key));
+            terminal.draw(|frame| ui::render(&mut app, frame)).unwrap();
+            let layout = |app: &App| {
+                (
+                    app.state.grid_columns,
+                    app.state.grid_page_size,
+                    app.state.log_view.height,
+                )
+            };
+            let before = layout(&app);
+
+            render(&mut app, (200, 60)).unwrap();
+            assert_eq!(layout(&app), before);
+        }
+    }
+
+    #[test]
+    fn ansi() {
+        let buffer = render(&mut app(), (40, 10)).unwrap();
+        let ansi = to_ansi(&buffer);
+        assert_eq!(ansi.lines().count(), 10);
+        assert!(ansi.contains("\x1b[0;31;49m"), "{ansi:?}");
+        assert!(ansi.lines().all(|line| line.ends_with("\x1b[0m")));
+    }
+
+    #[test]
+    fn svg() {
+        let buffer = render(&mut app(), (40, 10)).unwrap();
+        let svg = to_svg(&buffer);
+        assert!(svg.starts_with("<svg"));
+        assert!(svg.trim_end().ends_with("</svg>"));
+        assert_eq!(svg.matches("<text").count(), 10);
+        assert_eq!(xml_escape("a<b & c>"), "a&lt;b &amp; c&gt;");
+    }
+}
@@ -0,0 +1,292 @@
+use serde_json::{json, Map, Value};
+
+use crate::{
+    app::{AppState, Timeseries},
+    ui::format_value,
+};
+
+/// Summary statistics of a series, points are `(step, value)` pairs
+#[derive(Debug, Clone, PartialEq)]
+pub struct SeriesStats {
+    pub count: usize,
+    pub first: (f64, f64),
+    pub last: (f64, f64),
+    /// First point with the smallest value
+    pub min: (f64, f64),
+    /// First point with the largest value
+    pub max: (f64, f64),
+    /// Mean of the last `window` values
+    pub recent_mean: f64,
+    /// Standard deviation of the last `window` values
+    pub recent_std: f64,
+    /// Change per step of a least squares line through the last `window` points, `None`
+    /// if they're all at the same step
+    pub recent_slope: Option<f64>,
+}
+
+/// Words of metric names that are better when higher, all other metrics are minimized
+const HIGHER_IS_BETTER: [&str; 17] = [
+    "acc",
+    "accuracy",
+    "auc",
+    "auroc",
+    "bleu",
+    "f1",
+    "iou",
+    "map",
+    "miou",
+    "precision",
+    "recall",
+    "return",
+    "returns",
+    "reward",
+    "rewards",
+    "score",
+    "top",
+];
+
+/// Whether `metric` improves when it increases, guessed from its name
+///
+/// The name is split into words at all characters but letters and digits, e.g. `val_acc`
+/// or `eval/mAP50`. A word counts if it's one of [`HIGHER_IS_BETTER`], also with trailing
+/// digits, so `heatmap_loss` or `val_mape` are minimized.
+pub fn higher_is_better(metric: &str) -> bool {
+    let metric = metric.to_lowercase();
+    metric.split(|c: char| !c.is_alphanumeric()).any(|word| {
+        let name = word.trim_end_matches(|c: char| c.is_ascii_digit());
+        HIGHER_IS_BETTER.contains(&word) || HIGHER_IS_BETTER.contains(&name)
+    })
+}
+
+/// Whether the metric of the series `key` is maximized, by `--maximize` if it's given
+/// or else guessed with [`higher_is_better`]
+pub fn is_maximized(state: &AppState, key: &str) -> bool {
+    let (metric, _) = state.split_key(key);
+    match &state.maximize {
+        Some(maximize) => maximize.is_match(metric),
+        None => higher_is_better(metric),
+    }
+}
+
+impl SeriesStats {
+    /// Computes the statistics of `data`, or `None` if it has no points
+    pub fn new(data: &Timeseries, window: usize) -> Option<Self> {
+        let points = data.get_data();
+        let first = *points.first()?;
+        let last = *points.last()?;
+        let min = points
+            .iter()
+            .copied()
+            .reduce(|min, point| if point.1 < min.1 { point } else { min })?;
+        let max = points
+            .iter()
+            .copied()
+            .reduce(|max, point| if point.1 > max.1 { point } else { max })?;
+        let recent = &points[points.len().saturating_sub(window.max(1))..];
+        let recent_mean = recent.iter().map(|(_, val)| val).sum::<f64>() / recent.len() as f64;
+        let recent_std = (recent
+            .iter()
+            .map(|(_, val)| (val - recent_mean).powi(2))
+            .sum::<f64>()
+            / recent.len() as f64)
+            .sqrt();
+        let mean_t = recent.iter().map(|(t, _)| t).sum::<f64>() / recent.len() as f64;
+        let (covariance, variance) = recent.iter().fold((0.0, 0.0), |(cov, var), (t, val)| {
+            (
+                cov + (t - mean_t) * (val - recent_mean),
+                var + (t - mean_t).powi(2),
+            )
+        });
+        let recent_slope = (variance > 0.0).then(|| covariance / variance);
+
+        Some(Self {
+            count: points.len(),
+            first,
+            last,
+            min,
+            max,
+            recent_mean,
+            recent_std,
+            recent_slope,
+        })
+    }
+
+    /// Returns the largest point if the series is `maximized`, or else the smallest,
+    /// see [`is_maximized`]
+    pub fn best(&self, maximized: bool) -> (f64, f64) {
+        if maximized {
+            self.max
+        } else {
+            self.min
+        }
+    }
+}
+
+/// Returns a table with the statistics of all series, one row per series
+pub fn summary_table(state: &AppState, window: usize) -> String {
+    let header = [
+        "metric".to_owned(),
+        "count".to_owned(),
+        "first".to_owned(),
+        "last".to_owned(),
+        "min (step)".to_owned(),
+        "max (step)".to_owned(),
+        format!("mean last {window}"),
+    ];
+    let mut rows = vec![header];
+    for key in state.sorted_keys() {
+        let Some(stats) = SeriesStats::new(&state.data[&key], window) else {
+            continue;
+        };
+        let with_step = |(t, val): (f64, f64)| format!("{} ({t})", format_value(val));
+        rows.push([
+            key,
+            stats.count.to_string(),
+            format_value(stats.first.1),
+            format_value(stats.last.1),
+            with_step(stats.min),
+            with_step(stats.max),
+            format_value(stats.recent_mean),
+        ]);
+    }
+
+    let widths: Vec<usize> = (0..rows[0].len())
+        .map(|col| rows.iter().map(|row| row[col].len()).max().unwrap_or(0))
+        .collect();
+    let mut table = String::new();
+    for row in rows {
+        let cells: Vec<String> = row
+            .iter()
+            .zip(&widths)
+            .enumerate()
+            .map(|(col, (cell, width))| match col {
+                0 => format!("{cell:<width$}"),
+                _ => format!("{cell:>width$}"),
+            })
+            .collect();
+        table += cells.join("  ").trim_end();
+        table.push('\n');
+    }
+    table
+}
+
+/// Returns the statistics of all series as JSON object keyed by series
+pub fn summary_json(state: &AppState, window: usize) -> Value {
+    let point = |(t, val): (f64, f64)| json!({ "step": t, "value": val });
+    let mut summary = Map::new();
+    for key in state.sorted_keys() {
+        let Some(stats) = SeriesStats::new(&state.data[&key], window) else {
+            continue;
+        };
+        summary.insert(
+            key,
+            json!({
+                "count": stats.count,
+                "first": point(stats.first),
+                "last": point(stats.last),
+                "min": point(stats.min),
+                "max": point(stats.max),
+                "recent_mean": stats.recent_mean,
+                "recent_window": window,
+            }),
+        );
+    }
+    Value::Object(summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::App;
+    use regex::Regex;
+
+    fn app() -> App {
+        let mut app = App::new(5, 1.0);
+        for (step, loss) in [(10, 4.0), (20, 1.0), (30, 3.0), (40, 1.0), (50, 2.0)] {
+            app.process_line(&format!("Step {step} loss {loss}"));
+        }
+        app.process_line("Step 50 acc 0.5");
+        app
+    }
+
+    #[test]
+    fn maximized_metrics() {
+        for metric in [
+            "eval_acc",
+            "accuracy",
+            "val/mAP50",
+            "top1-acc",
+            "f1",
+            "episode_reward",
+        ] {
+            assert!(higher_is_better(metric), "{metric}");
+        }
+        for metric in [
+            "loss",
+            "val_mape",
+            "heatmap_loss",
+            "bitmap",
+            "accum_loss",
+            "lr",
+        ] {
+            assert!(!higher_is_better(metric), "{metric}");
+        }
+
+        let mut app = app();
+        app.state.runs = vec!["a".into(), "b".into()];
+        assert!(is_maximized(&app.state, "acc@a"));
+        app.state.maximize = Some(Regex::new("^(loss|mape)$").unwrap());
+        assert!(is_maximized(&app.state, "loss@b"));
+        assert!(!is_maximized(&app.state, "acc@a"));
+    }
+
+    #[test]
+    fn series_stats() {
+        let app = app();
+        let stats = SeriesStats::new(&app.state.data["loss"], 2).unwrap();
+        assert_eq!(
+            stats,
+            SeriesStats {
+                count: 5,
+                first: (10.0, 4.0),
+                last: (50.0, 2.0),
+                min: (20.0, 1.0),
+                max: (10.0, 4.0),
+                recent_mean: 1.5,
+                recent_std: 0.5,
+                recent_slope: Some(0.1),
+            }
+        );
+        assert_eq!(stats.best(false), (20.0, 1.0));
+        assert_eq!(stats.best(true), (10.0, 4.0));
+
+        let stats = SeriesStats::new(&app.state.data["loss"], 100).unwrap();
+        assert_eq!(stats.recent_mean, 2.2);
+        assert!((stats.recent_slope.unwrap() - -0.04).abs() < 1e-9);
+        let stats = SeriesStats::new(&app.state.data["acc"], 2).unwrap();
+        assert_eq!((stats.recent_std, stats.recent_slope), (0.0, None));
+        assert!(SeriesStats::new(&Timeseries::default(), 2).is_none());
+    }
+
+    #[test]
+    fn table() {
+        assert_eq!(
+            summary_table(&app().state, 2),
+            "metric  count   first    last   min (step)   max (step)  mean last 2\n\
+             acc         1  0.5000  0.5000  0.5000 (50)  0.5000 (50)       0.5000\n\
+             loss        5  4.0000  2.0000  1.0000 (20)  4.0000 (10)       1.5000\n"
+        );
+    }
+
+    #[test]
+    fn json() {
+        let summary = summary_json(&app().state, 2);
+        assert_eq!(summary["loss"]["count"], 5);
+        assert_eq!(
+            summary["loss"]["min"],
+            json!({ "step": 20.0, "value": 1.0 })
+        );
+        assert_eq!(summary["loss"]["recent_mean"], 1.5);
+        assert_eq!(summary["acc"]["last"]["value"], 0.5);
+    }
+}
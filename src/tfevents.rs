@@ -0,0 +1,582 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    time::{Duration, UNIX_EPOCH},
+};
+
+use anyhow::{bail, Context, Result};
+
+use crate::app::AppState;
+
+/// Scalar value of a TensorBoard event file
+#[derive(Debug, Clone, PartialEq)]
+pub struct Scalar {
+    pub tag: String,
+    pub step: i64,
+    /// Seconds since the unix epoch
+    pub wall_time: f64,
+    pub value: f64,
+}
+
+/// Returns whether `path` is named like a TensorBoard event file, `events.out.tfevents.*`
+pub fn is_event_file(path: &Path) -> bool {
+    path.file_name()
+        .is_some_and(|name| name.to_string_lossy().contains("tfevents"))
+}
+
+/// Returns whether `path` is read as event files, which is the case for directories
+pub fn is_event_input(path: &Path) -> bool {
+    path.is_dir() || is_event_file(path)
+}
+
+/// Returns the runs of an event file or a log directory with their event files
+///
+/// Event files in a directory are searched recursively, every directory containing
+/// some is a run. Runs in subdirectories are named `name/subdirectory`. Symbolic links
+/// to directories aren't followed, they could form a loop.
+pub fn find_runs(name: &str, path: &Path) -> Result<Vec<(String, Vec<PathBuf>)>> {
+    if !path.is_dir() {
+        return Ok(vec![(name.to_owned(), vec![path.to_owned()])]);
+    }
+
+    let mut runs = Vec::new();
+    let mut dirs = vec![path.to_owned()];
+    while let Some(dir) = dirs.pop() {
+        let mut files = Vec::new();
+        let entries =
+            std::fs::read_dir(&dir).with_context(|| format!("Failed to read {}", dir.display()))?;
+        for entry in entries {
+            let entry = entry?;
+            let entry_path = entry.path();
+            if entry.file_type()?.is_dir() {
+                dirs.push(entry_path);
+            } else if is_event_file(&entry_path) {
+                files.push(entry_path);
+            }
+        }
+        if files.is_empty() {
+            continue;
+        }
+        // The file names start with the creation time, so a resumed run is read in order
+        files.sort();
+        let subdir = dir.strip_prefix(path).unwrap_or(&dir);
+        let run = if subdir.as_os_str().is_empty() {
+            name.to_owned()
+        } else {
+            format!("{name}/{}", subdir.to_string_lossy())
+        };
+        runs.push((run.replace('@', "_"), files));
+    }
+    if runs.is_empty() {
+        bail!("No TensorBoard event files found in {}", path.display());
+    }
+    runs.sort();
+    Ok(runs)
+}
+
+/// Loads the scalars of all `files` as series of the run with index `run`
+///
+/// The values keep their steps and wall times and are sorted by step. Of values at the
+/// same step, e.g. repeated by a run resumed from a checkpoint, the latest one is kept.
+/// The first series is displayed if none is yet.
+pub fn load(state: &mut AppState, run: usize, files: &[PathBuf]) -> Result<()> {
+    let mut series: HashMap<String, Vec<Scalar>> = HashMap::new();
+    for path in files {
+        let data =
+            std::fs::read(path).with_context(|| format!("Failed to read {}", path.display()))?;
+        for scalar in read_scalars(&data) {
+            series.entry(scalar.tag.clone()).or_default().push(scalar);
+        }
+    }
+    for (tag, mut scalars) in series {
+        // The latest value of a step comes first and is kept
+        scalars.sort_by(|a, b| {
+            a.step
+                .cmp(&b.step)
+                .then(b.wall_time.total_cmp(&a.wall_time))
+        });
+        scalars.dedup_by_key(|scalar| scalar.step);
+        let data = state.data.entry(state.series_key(run, &tag)).or_default();
+        for scalar in scalars {
+            let wall_time = Duration::try_from_secs_f64(scalar.wall_time).unwrap_or_default();
+            data.push(scalar.step as f64, scalar.value, UNIX_EPOCH + wall_time);
+        }
+    }
+    if state.display_keys.is_empty() {
+        state.display_keys = state.sorted_keys().into_iter().take(1).collect();
+    }
+    Ok(())
+}
+
+/// Returns the scalars of the events in the TFRecord file `data`
+///
+/// Reading stops at a truncated or corrupted record, e.g. one that is still being written.
+pub fn read_scalars(data: &[u8]) -> Vec<Scalar> {
+    let mut scalars = Vec::new();
+    for event in records(data) {
+        parse_event(event, &mut scalars);
+    }
+    scalars
+}
+
+/// Returns the payloads of the records of a TFRecord file
+///
+/// A record is the payload length as `u64`, its masked CRC, the payload and its masked CRC.
+fn records(mut data: &[u8]) -> Vec<&[u8]> {
+    let mut records = Vec::new();
+    while data.len() >= 12 {
+        let len_bytes = &data[..8];
+        let len_crc = u32::from_le_bytes(data[8..12].try_into().unwrap());
+        if masked_crc32c(len_bytes) != len_crc {
+            break;
+        }
+        let len = u64::from_le_bytes(len_bytes.try_into().unwrap());
+        let Some(end) = usize::try_from(len)
+            .ok()
+            .and_then(|len| len.checked_add(16))
+        else {
+            break;
+        };
+        if data.len() < end {
+            break;
+        }
+        let payload = &data[12..end - 4];
+        let payload_crc = u32::from_le_bytes(data[end - 4..end].try_into().unwrap());
+        if masked_crc32c(payload) != payload_crc {
+            break;
+        }
+        records.push(payload);
+        data = &data[end..];
+    }
+    records
+}
+
+/// Adds the scalars of an `Event` message to `scalars`
+fn parse_event(event: &[u8], scalars: &mut Vec<Scalar>) {
+    let mut wall_time = 0.0;
+    let mut step = 0;
+    let mut summaries = Vec::new();
+    for (number, field) in fields(event) {
+        match (number, field) {
+            (1, Field::Fixed64(bits)) => wall_time = f64::from_bits(bits),
+            (2, Field::Varint(value)) => step = value as i64,
+            (5, Field::Bytes(summary)) => summaries.push(summary),
+            _ => {}
+        }
+    }
+
+    for summary in summaries {
+        for (number, field) in fields(summary) {
+            let (1, Field::Bytes(value)) = (number, field) else {
+                continue;
+            };
+            if let Some((tag, value)) = parse_value(value) {
+                scalars.push(Scalar {
+                    tag,
+                    step,
+                    wall_time,
+                    value,
+                });
+            }
+        }
+    }
+}
+
+/// Returns the tag and value of a `Summary.Value` message if it's a scalar
+fn parse_value(value: &[u8]) -> Option<(String, f64)> {
+    let mut tag = None;
+    let mut node_name = None;
+    let mut scalar = None;
+    for (number, field) in fields(value) {
+        match (number, field) {
+            (1, Field::Bytes(bytes)) => tag = Some(String::from_utf8_lossy(bytes).into_owned()),
+            (2, Field::Fixed32(bits)) => scalar = Some(f32::from_bits(bits) as f64),
+            (7, Field::Bytes(bytes)) => {
+                node_name = Some(String::from_utf8_lossy(bytes).into_owned())
+            }
+            (8, Field::Bytes(tensor)) => scalar = scalar.or_else(|| tensor_scalar(tensor)),
+            _ => {}
+        }
+    }
+    Some((tag.or(node_name)?, scalar?))
+}
+
+/// Returns the value of a `TensorProto` with a single number, as written by `tf.summary.scalar`
+fn tensor_scalar(tensor: &[u8]) -> Option<f64> {
+    let mut dtype = 0;
+    let mut num_elements = 1;
+    let mut content = None;
+    let mut values = Vec::new();
+    for (number, field) in fields(tensor) {
+        match (number, field) {
+            (1, Field::Varint(value)) => dtype = value,
+            (2, Field::Bytes(shape)) => {
+                for (number, field) in fields(shape) {
+                    let (2, Field::Bytes(dim)) = (number, field) else {
+                        continue;
+                    };
+                    for (number, field) in fields(dim) {
+                        if let (1, Field::Varint(size)) = (number, field) {
+                            num_elements = size.saturating_mul(num_elements);
+                        }
+                    }
+                }
+            }
+            (4, Field::Bytes(bytes)) => content = Some(bytes),
+            // float_val and double_val, packed or not
+            (5, Field::Bytes(bytes)) => values.extend(
+                bytes
+                    .chunks_exact(4)
+                    .map(|b| f32::from_le_bytes(b.try_into().unwrap()) as f64),
+            ),
+            (5, Field::Fixed32(bits)) => values.push(f32::from_bits(bits) as f64),
+            (6, Field::Bytes(bytes)) => values.extend(
+                bytes
+                    .chunks_exact(8)
+                    .map(|b| f64::from_le_bytes(b.try_into().unwrap())),
+            ),
+            (6, Field::Fixed64(bits)) => values.push(f64::from_bits(bits)),
+            // int_val and int64_val
+            (7 | 10, Field::Bytes(mut bytes)) => {
+                while let Some(value) = read_varint(&mut bytes) {
+                    values.push(value as i64 as f64);
+                }
+            }
+            (7 | 10, Field::Varint(value)) => values.push(value as i64 as f64),
+            _ => {}
+        }
+    }
+    if num_elements != 1 {
+        return None;
+    }
+
+    if let Some(content) = content {
+        // DT_FLOAT, DT_DOUBLE, DT_INT32 and DT_INT64, little endian
+        return match (dtype, content.len()) {
+            (1, 4) => Some(f32::from_le_bytes(content.try_into().unwrap()) as f64),
+            (2, 8) => Some(f64::from_le_bytes(content.try_into().unwrap())),
+            (3, 4) => Some(i32::from_le_bytes(content.try_into().unwrap()) as f64),
+            (9, 8) => Some(i64::from_le_bytes(content.try_into().unwrap()) as f64),
+            _ => None,
+        };
+    }
+    match values.as_slice() {
+        [value] => Some(*value),
+        _ => None,
+    }
+}
+
+/// Value of a protobuf field, depending on its wire type
+enum Field<'a> {
+    Varint(u64),
+    Fixed64(u64),
+    Bytes(&'a [u8]),
+    Fixed32(u32),
+}
+
+/// Returns the `(field number, value)` pairs of a protobuf message, stopping at malformed data
+fn fields(mut message: &[u8]) -> impl Iterator<Item = (u64, Field<'_>)> {
+    std::iter::from_fn(move || {
+        let field = read_field(&mut message);
+        if field.is_none() {
+            message = &[];
+        }
+        field
+    })
+}
+
+fn read_field<'a>(message: &mut &'a [u8]) -> Option<(u64, Field<'a>)> {
+    let key = read_varint(message)?;
+    let field = match key & 7 {
+        0 => Field::Varint(read_varint(message)?),
+        1 => Field::Fixed64(u64::from_le_bytes(take(message, 8)?.try_into().ok()?)),
+        2 => {
+            let len = usize::try_from(read_varint(message)?).ok()?;
+            Field::Bytes(take(message, len)?)
+        }
+        5 => Field::Fixed32(u32::from_le_bytes(take(message, 4)?.try_into().ok()?)),
+        // Groups are deprecated and not used by event files
+        _ => return None,
+    };
+    Some((key >> 3, field))
+}
+
+fn read_varint(bytes: &mut &[u8]) -> Option<u64> {
+    let mut value = 0;
+    for shift in (0..64).step_by(7) {
+        let (byte, rest) = bytes.split_first()?;
+        *bytes = rest;
+        value |= u64::from(byte & 0x7f) << shift;
+        if byte & 0x80 == 0 {
+            return Some(value);
+        }
+    }
+    None
+}
+
+fn take<'a>(bytes: &mut &'a [u8], len: usize) -> Option<&'a [u8]> {
+    if bytes.len() < len {
+        return None;
+    }
+    let (taken, rest) = bytes.split_at(len);
+    *bytes = rest;
+    Some(taken)
+}
+
+/// Lookup table of the CRC-32C (Castagnoli) polynomial, in reversed bit order
+const CRC32C_TABLE: [u32; 256] = {
+    let mut table = [0; 256];
+    let mut idx = 0;
+    while idx < 256 {
+        let mut crc = idx as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ 0x82f6_3b78
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[idx] = crc;
+        idx += 1;
+    }
+    table
+};
+
+fn crc32c(data: &[u8]) -> u32 {
+    let crc = data.iter().fold(!0, |crc: u32, byte| {
+        CRC32C_TABLE[((crc ^ u32::from(*byte)) & 0xff) as usize] ^ (crc >> 8)
+    });
+    !crc
+}
+
+/// CRC as stored in TFRecord files, rotated so that CRCs of data containing CRCs work well
+fn masked_crc32c(data: &[u8]) -> u32 {
+    crc32c(data).rotate_right(15).wrapping_add(0xa282_ead8)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::App;
+
+    fn varint(mut value: u64, out: &mut Vec<u8>) {
+        while value >= 0x80 {
+            out.push(value as u8 | 0x80);
+            value >>= 7;
+        }
+        out.push(value as u8);
+    }
+
+    fn bytes_field(number: u64, bytes: &[u8], out: &mut Vec<u8>) {
+        varint(number << 3 | 2, out);
+        varint(bytes.len() as u64, out);
+        out.extend_from_slice(bytes);
+    }
+
+    /// Event with one summary value, which is built by `value`
+    fn event(step: u64, wall_time: f64, value: impl Fn(&mut Vec<u8>)) -> Vec<u8> {
+        let mut summary_value = Vec::new();
+        value(&mut summary_value);
+        let mut summary = Vec::new();
+        bytes_field(1, &summary_value, &mut summary);
+
+        let mut event = Vec::new();
+        varint(1 << 3 | 1, &mut event);
+        event.extend_from_slice(&wall_time.to_le_bytes());
+        varint(2 << 3, &mut event);
+        varint(step, &mut event);
+        bytes_field(5, &summary, &mut event);
+        event
+    }
+
+    fn simple_value(step: u64, tag: &str, value: f32) -> Vec<u8> {
+        simple_value_at(step, 1.7e9 + step as f64, tag, value)
+    }
+
+    fn simple_value_at(step: u64, wall_time: f64, tag: &str, value: f32) -> Vec<u8> {
+        event(step, wall_time, |out| {
+            bytes_field(1, tag.as_bytes(), out);
+            varint(2 << 3 | 5, out);
+            out.extend_from_slice(&value.to_le_bytes());
+        })
+    }
+
+    fn tensor_value(step: u64, tag: &str, tensor: &[u8]) -> Vec<u8> {
+        event(step, 1.7e9, |out| {
+            bytes_field(1, tag.as_bytes(), out);
+            bytes_field(8, tensor, out);
+        })
+    }
+
+    fn record(payload: &[u8]) -> Vec<u8> {
+        let len = (payload.len() as u64).to_le_bytes();
+        let mut record = len.to_vec();
+        record.extend_from_slice(&masked_crc32c(&len).to_le_bytes());
+        record.extend_from_slice(payload);
+        record.extend_from_slice(&masked_crc32c(payload).to_le_bytes());
+        record
+    }
+
+    fn file(events: &[Vec<u8>]) -> Vec<u8> {
+        // The first event of a file only contains the file version
+        let mut version = Vec::new();
+        bytes_field(3, b"brain.Event:2", &mut version);
+        let mut data = record(&version);
+        for event in events {
+            data.extend(record(event));
+        }
+        data
+    }
+
+    #[test]
+    fn crc() {
+        assert_eq!(crc32c(b"123456789"), 0xe306_9283);
+    }
+
+    #[test]
+    fn simple_values() {
+        let data = file(&[simple_value(1, "loss", 2.0), simple_value(2, "loss", 0.5)]);
+        assert_eq!(
+            read_scalars(&data),
+            [
+                Scalar {
+                    tag: "loss".into(),
+                    step: 1,
+                    wall_time: 1.7e9 + 1.0,
+                    value: 2.0
+                },
+                Scalar {
+                    tag: "loss".into(),
+                    step: 2,
+                    wall_time: 1.7e9 + 2.0,
+                    value: 0.5
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn tensors() {
+        let shape = |dims: &[u64]| {
+            let mut shape = Vec::new();
+            for size in dims {
+                let mut dim = Vec::new();
+                varint(1 << 3, &mut dim);
+                varint(*size, &mut dim);
+                bytes_field(2, &dim, &mut shape);
+            }
+            shape
+        };
+        let tensor = |dtype: u64, dims: &[u64], field: u64, bytes: &[u8]| {
+            let mut tensor = Vec::new();
+            varint(1 << 3, &mut tensor);
+            varint(dtype, &mut tensor);
+            bytes_field(2, &shape(dims), &mut tensor);
+            bytes_field(field, bytes, &mut tensor);
+            tensor
+        };
+
+        let data = file(&[
+            // float_val of a scalar, as written by tf.summary.scalar
+            tensor_value(1, "float", &tensor(1, &[], 5, &0.25f32.to_le_bytes())),
+            tensor_value(2, "double", &tensor(2, &[1], 4, &0.5f64.to_le_bytes())),
+            tensor_value(3, "int", &tensor(9, &[], 10, &[0x96, 0x01])),
+            // Histograms and other tensors with several values are skipped
+            tensor_value(4, "hist", &tensor(1, &[2], 5, &[0; 8])),
+            tensor_value(5, "text", &tensor(7, &[], 8, b"hello")),
+        ]);
+        let scalars: Vec<_> = read_scalars(&data)
+            .into_iter()
+            .map(|scalar| (scalar.tag, scalar.step, scalar.value))
+            .collect();
+        assert_eq!(
+            scalars,
+            [
+                ("float".into(), 1, 0.25),
+                ("double".into(), 2, 0.5),
+                ("int".into(), 3, 150.0)
+            ]
+        );
+    }
+
+    #[test]
+    fn broken_records() {
+        let mut data = file(&[simple_value(1, "loss", 2.0), simple_value(2, "loss", 1.0)]);
+        assert_eq!(read_scalars(&data[..data.len() - 3]).len(), 1);
+
+        let last = data.len() - 5;
+        data[last] ^= 0xff;
+        assert_eq!(read_scalars(&data).len(), 1);
+        assert!(read_scalars(b"loss 1.0\n").is_empty());
+    }
+
+    #[test]
+    fn runs_of_directory() {
+        let root = std::env::temp_dir().join(format!("ezboard-{}-tfevents", std::process::id()));
+        for (dir, files) in [
+            ("", vec!["events.out.tfevents.1.host"]),
+            ("a", vec![]),
+            ("b/eval", vec!["events.out.tfevents.3.host", "notes.txt"]),
+            ("c", vec![]),
+        ] {
+            std::fs::create_dir_all(root.join(dir)).unwrap();
+            for (idx, name) in files.iter().enumerate() {
+                let data = file(&[simple_value(idx as u64, "loss", idx as f32)]);
+                std::fs::write(root.join(dir).join(name), data).unwrap();
+            }
+        }
+        // A run resumed at step 1, which is written again later, after step 2
+        let resumed = file(&[
+            simple_value_at(1, 1.8e9, "loss", 5.0),
+            simple_value_at(3, 1.8e9, "loss", 3.0),
+        ]);
+        std::fs::write(root.join("a/events.out.tfevents.2.host"), resumed).unwrap();
+        let first = file(&[
+            simple_value(2, "loss", 2.0),
+            simple_value(0, "loss", 0.0),
+            simple_value(1, "loss", 1.0),
+        ]);
+        std::fs::write(root.join("a/events.out.tfevents.1.host"), first).unwrap();
+        #[cfg(unix)]
+        std::os::unix::fs::symlink(&root, root.join("b/loop")).unwrap();
+
+        let runs = find_runs("logs", &root).unwrap();
+        let names: Vec<_> = runs.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(names, ["logs", "logs/a", "logs/b/eval"]);
+        let files: Vec<_> = runs[1]
+            .1
+            .iter()
+            .map(|path| path.file_name().unwrap())
+            .collect();
+        assert_eq!(
+            files,
+            ["events.out.tfevents.1.host", "events.out.tfevents.2.host"]
+        );
+        assert!(find_runs("c", &root.join("c")).is_err());
+
+        let mut app = App::new(5, 1.0);
+        app.state.runs = names.iter().map(|name| name.to_string()).collect();
+        for (run, (_, files)) in runs.iter().enumerate() {
+            load(&mut app.state, run, files).unwrap();
+        }
+        assert_eq!(
+            app.state.sorted_keys(),
+            ["loss@logs", "loss@logs/a", "loss@logs/b/eval"]
+        );
+        assert_eq!(app.state.display_keys, ["loss@logs"]);
+        let data = &app.state.data["loss@logs/a"];
+        assert_eq!(
+            data.get_data(),
+            &vec![(0.0, 0.0), (1.0, 5.0), (2.0, 2.0), (3.0, 3.0)]
+        );
+        assert_eq!(
+            data.get_timestamps()[..3],
+            [1_700_000_000, 1_800_000_000, 1_700_000_002]
+                .map(|secs| UNIX_EPOCH + Duration::from_secs(secs))
+        );
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
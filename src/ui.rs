@@ -1,73 +1,407 @@
+use std::time::{Duration, SystemTime};
+
 use ratatui::{
-    style::{Modifier, Style, Stylize},
+    layout::{Alignment, Constraint, Direction, Layout, Rect},
+    style::{Color, Modifier, Style, Stylize},
     symbols::Marker,
-    text::Line,
-    widgets::{Axis, Block, Borders, Chart, Dataset, GraphType, List, Paragraph},
+    text::{Line, Span},
+    widgets::{
+        block::{Position, Title},
+        Axis, Block, Borders, Chart, Dataset, GraphType, List, Paragraph,
+    },
     Frame,
 };
+use regex::Regex;
 
-use crate::app::App;
+use crate::{
+    app::{App, Timeseries},
+    input::LineSource,
+    log_view::PromptKind,
+    scale::YScale,
+    smoothing::Smoothing,
+    stats::{is_maximized, SeriesStats},
+    view::StepView,
+};
 
 #[derive(PartialEq, PartialOrd)]
 pub enum UiState {
     Plot,
     KeySelection,
     Passthrough,
+    Grid,
+}
+
+/// Time after the last line until a followed input is shown as idle
+const LIVE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Time a notice is shown
+const NOTICE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Width of the statistics panel next to the plot
+const STATS_PANEL_WIDTH: u16 = 30;
+
+/// Minimum width of a tile in the grid view
+const TILE_MIN_WIDTH: u16 = 32;
+/// Minimum height of a tile in the grid view
+const TILE_MIN_HEIGHT: u16 = 8;
+
+/// Colors of the plotted series, repeated if there are more series
+const SERIES_COLORS: [Color; 6] = [
+    Color::Red,
+    Color::Green,
+    Color::Yellow,
+    Color::Blue,
+    Color::Magenta,
+    Color::Cyan,
+];
+
+pub fn series_color(idx: usize) -> Color {
+    SERIES_COLORS[idx % SERIES_COLORS.len()]
 }
 
 pub fn plot(app: &mut App, frame: &mut Frame) {
     let area = frame.size();
 
-    let Some(ref key) = app.state.display_key else {
+    let keys = app.state.plotted_keys();
+    let series: Vec<_> = keys
+        .iter()
+        .filter_map(|key| Some((key, app.state.data.get(key)?)))
+        .collect();
+    if series.is_empty() {
         return;
-    };
+    }
 
-    let Some(data) = app.state.data.get(key) else {
+    let Some((first_t, last_t)) = app.state.display_t_bounds() else {
         return;
     };
-    let min_val = data.get_min();
-    let max_val = data.get_max();
+    let (first_t, last_t) = app.state.step_view.range(first_t, last_t);
+    let (min_val, max_val) = series
+        .iter()
+        .filter_map(|(_, data)| data.get_min_max_in(first_t, last_t))
+        .reduce(|(min, max), (lo, hi)| (f64::min(min, lo), f64::max(max, hi)))
+        .unwrap_or((0.0, 0.0));
+    let is_visible = |(t, _): &&(f64, f64)| (first_t..=last_t).contains(t);
+    let smoothing = app.state.smoothing.is_active();
+    let smoothed: Vec<_> = series
+        .iter()
+        .map(|(_, data)| data.smoothed(&app.state.smoothing))
+        .collect();
+
+    let raw: Vec<Vec<_>> = series
+        .iter()
+        .map(|(_, data)| data.get_data().iter().filter(is_visible).copied().collect())
+        .collect();
+    let scale = if app.state.is_log_scale(series[0].0) {
+        YScale::log_for(raw.iter().flatten().map(|(_, val)| val))
+    } else {
+        YScale::Linear
+    };
+    let scaled_raw: Vec<_> = raw.iter().map(|data| scale.apply_all(data)).collect();
+    let scaled: Vec<_> = smoothed
+        .iter()
+        .map(|data| {
+            let visible: Vec<_> = data.iter().filter(is_visible).copied().collect();
+            scale.apply_all(&visible)
+        })
+        .collect();
 
-    let dataset = Dataset::default()
-        .name(key.to_owned())
-        .data(data.get_data())
-        .marker(Marker::Braille)
-        .graph_type(GraphType::Line)
-        .red();
+    let cursor = app.state.cursor.and_then(|idx| {
+        let (key, data) = series[0];
+        let (t, val) = *smoothed[0].get(idx)?;
+        let (_, raw) = data.get_data()[idx];
+
+        let mut readout = vec![
+            format!("step {t}"),
+            format!("{key} {} (raw {})", format_value(val), format_value(raw)),
+        ];
+        for ((key, data), smoothed) in series.iter().zip(&smoothed).skip(1) {
+            let Some((t, val)) = data.nearest(t).map(|idx| smoothed[idx]) else {
+                continue;
+            };
+            readout.push(format!("{key} {} @ {t}", format_value(val)));
+        }
 
-    let time_step = app.state.max_t(&key) - 1.0;
+        let crosshair = vec![(t, scale.apply(min_val)), (t, scale.apply(max_val))];
+        let highlight = vec![(t, scale.apply(val))];
+        Some((crosshair, highlight, readout.join(" | ")))
+    });
 
+    let mut datasets = Vec::new();
+    if let Some((crosshair, _, _)) = &cursor {
+        datasets.push(
+            Dataset::default()
+                .data(crosshair)
+                .marker(Marker::Braille)
+                .graph_type(GraphType::Line)
+                .white(),
+        );
+    }
+    if smoothing {
+        for data in &scaled_raw {
+            datasets.push(
+                Dataset::default()
+                    .data(data)
+                    .marker(Marker::Braille)
+                    .graph_type(GraphType::Line)
+                    .dark_gray(),
+            );
+        }
+    }
+    for (idx, ((key, _), data)) in series.iter().zip(&scaled).enumerate() {
+        datasets.push(
+            Dataset::default()
+                .name(key.to_string())
+                .data(data)
+                .marker(Marker::Braille)
+                .graph_type(GraphType::Line)
+                .fg(series_color(idx)),
+        );
+    }
+    if let Some((_, highlight, _)) = &cursor {
+        datasets.push(
+            Dataset::default()
+                .data(highlight)
+                .marker(Marker::Block)
+                .graph_type(GraphType::Scatter)
+                .yellow(),
+        );
+    }
+
+    let x_title = match app.state.step_view {
+        StepView::Full => "Step".to_owned(),
+        StepView::Window { .. } => "Step (zoomed)".to_owned(),
+        StepView::Tail { width } => format!("Step (last {width})"),
+    };
     let x_axis = Axis::default()
-        .title("Step".red())
+        .title(x_title.red())
         .white()
-        .bounds([0.0, time_step])
-        .labels(vec!["0.0".into(), format!("{time_step}").into()]);
+        .bounds([first_t, last_t])
+        .labels(vec![
+            format!("{first_t}").into(),
+            format!("{last_t}").into(),
+        ]);
 
+    let mut y_title = match series.as_slice() {
+        [(key, _)] => key.to_string(),
+        // The same metric of several runs
+        [(key, _), rest @ ..] => {
+            let metric = |key: &str| match app.state.split_key(key) {
+                (metric, Some(_)) => Some(metric.to_owned()),
+                _ => None,
+            };
+            match metric(key) {
+                Some(m) if rest.iter().all(|(key, _)| metric(key).as_ref() == Some(&m)) => m,
+                _ => "Value".into(),
+            }
+        }
+        _ => "Value".into(),
+    };
+    if let Some(note) = scale.note() {
+        y_title = format!("{y_title} ({note})");
+    }
+    let y_labels = match scale {
+        YScale::Linear => vec![format!("{min_val}").into(), format!("{max_val}").into()],
+        _ => scale
+            .ticks(min_val, max_val)
+            .into_iter()
+            .map(|tick| format_value(tick).into())
+            .collect(),
+    };
     let y_axis = Axis::default()
-        .title(key.clone().red())
+        .title(y_title.red())
         .white()
-        .bounds([min_val, max_val])
-        .labels(vec![
-            format!("{min_val}").into(),
-            format!("{max_val}").into(),
-        ]);
+        .bounds([scale.apply(min_val), scale.apply(max_val)])
+        .labels(y_labels);
 
-    frame.render_widget(
-        Chart::new(vec![dataset])
-            .red()
-            .x_axis(x_axis)
-            .y_axis(y_axis),
-        area,
-    );
+    let area = match &cursor {
+        Some((_, _, readout)) => {
+            let [chart_area, status_area] = Layout::default()
+                .direction(Direction::Vertical)
+                .constraints([Constraint::Min(0), Constraint::Length(1)])
+                .areas(area);
+            frame.render_widget(Paragraph::new(readout.as_str()).yellow(), status_area);
+            chart_area
+        }
+        None => area,
+    };
+    let area = match finished_banner(app) {
+        Some(banner) => {
+            let [banner_area, chart_area] = Layout::default()
+                .direction(Direction::Vertical)
+                .constraints([Constraint::Length(1), Constraint::Min(0)])
+                .areas(area);
+            frame.render_widget(Paragraph::new(banner).reversed(), banner_area);
+            chart_area
+        }
+        None => area,
+    };
+    let area = if app.state.stats_panel {
+        let [chart_area, panel_area] = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints([Constraint::Min(0), Constraint::Length(STATS_PANEL_WIDTH)])
+            .areas(area);
+        stats_panel(app, &series, frame, panel_area);
+        chart_area
+    } else {
+        area
+    };
+
+    let mut chart = Chart::new(datasets)
+        .red()
+        .x_axis(x_axis)
+        .y_axis(y_axis)
+        .hidden_legend_constraints((Constraint::Ratio(1, 2), Constraint::Ratio(1, 2)));
+    let mut titles = Vec::new();
+    if smoothing {
+        titles.push(Title::from(app.state.smoothing.label().white()));
+    }
+    if let Some(status) = input_status(app) {
+        titles.push(Title::from(status).alignment(Alignment::Right));
+    }
+    if let Some(notice) = notice(app) {
+        titles.push(Title::from(notice).position(Position::Bottom));
+    }
+    if !titles.is_empty() {
+        chart = chart.block(titles.into_iter().fold(Block::default(), Block::title));
+    }
+    frame.render_widget(chart, area);
+}
+
+/// Renders the statistics of the plotted series, in the colors of their lines
+fn stats_panel(app: &App, series: &[(&String, &Timeseries)], frame: &mut Frame, area: Rect) {
+    let window = app.state.stats_window;
+    let mut lines = Vec::new();
+    for (idx, (key, data)) in series.iter().enumerate() {
+        let Some(stats) = SeriesStats::new(data, window) else {
+            continue;
+        };
+        if !lines.is_empty() {
+            lines.push(Line::default());
+        }
+        let row = |label: &str, value: String| {
+            Line::from(vec![format!("{label:<8}").dark_gray(), value.into()])
+        };
+        let (last_t, last) = stats.last;
+        let (best_t, best) = stats.best(is_maximized(&app.state, key));
+        let slope = match stats.recent_slope {
+            Some(slope) => format!("{}/step", format_value(slope)),
+            None => "-".to_owned(),
+        };
+
+        lines.push(Line::from(key.as_str()).fg(series_color(idx)).bold());
+        lines.push(row("last", format!("{} @ {last_t}", format_value(last))));
+        lines.push(row("best", format!("{} @ {best_t}", format_value(best))));
+        lines.push(row(
+            "mean",
+            format!(
+                "{} ± {}",
+                format_value(stats.recent_mean),
+                format_value(stats.recent_std)
+            ),
+        ));
+        lines.push(row("slope", slope));
+        lines.push(row("points", stats.count.to_string()));
+        if let Some(updated) = data.get_timestamps().last() {
+            let age = SystemTime::now()
+                .duration_since(*updated)
+                .unwrap_or_default();
+            lines.push(row("updated", format!("{} ago", format_duration(age))));
+        }
+    }
+
+    let block = Block::default()
+        .borders(Borders::LEFT)
+        .title(format!("Stats (window {window})").white());
+    frame.render_widget(Paragraph::new(lines).block(block), area);
+}
+
+/// Formats a duration as hours, minutes and seconds
+pub fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    match (secs / 3600, secs / 60 % 60, secs % 60) {
+        (0, 0, s) => format!("{s}s"),
+        (0, m, s) => format!("{m}m {s}s"),
+        (h, m, s) => format!("{h}h {m}m {s}s"),
+    }
+}
+
+/// Returns a banner with the final stats of the displayed series once the input ended
+fn finished_banner(app: &App) -> Option<String> {
+    let finished_at = app.state.finished_at?;
+
+    let mut banner = vec![format!(
+        "Run finished after {}{}, {} lines read",
+        format_duration(finished_at - app.state.started_at),
+        exit_note(app),
+        app.state.lines_read
+    )];
+    for key in &app.state.display_keys {
+        let Some(data) = app.state.data.get(key) else {
+            continue;
+        };
+        let Some((_, last)) = data.get_data().last() else {
+            continue;
+        };
+        banner.push(format!(
+            "{key}: last {} min {} max {}",
+            format_value(*last),
+            format_value(data.get_min()),
+            format_value(data.get_max())
+        ));
+    }
+    Some(banner.join(" | "))
+}
+
+/// Returns how the command exited, e.g. ` (exit status: 1)`, if the input is a command
+fn exit_note(app: &App) -> String {
+    app.state
+        .exit_status
+        .map(|status| format!(" ({status})"))
+        .unwrap_or_default()
+}
+
+/// Returns the notice if it was set recently
+fn notice(app: &App) -> Option<Span<'_>> {
+    let (set_at, notice) = app.state.notice.as_ref()?;
+    (set_at.elapsed() < NOTICE_TIMEOUT).then(|| notice.as_str().yellow())
+}
+
+/// Returns whether a followed input is still receiving lines
+fn input_status(app: &App) -> Option<Span<'static>> {
+    if !app.state.follow {
+        return None;
+    }
+
+    let status = match app.state.last_input.map(|t| t.elapsed()) {
+        Some(elapsed) if elapsed < LIVE_TIMEOUT => "● live".green(),
+        Some(elapsed) => format!("○ idle for {}s", elapsed.as_secs()).yellow(),
+        None => "○ waiting for input".yellow(),
+    };
+    Some(status)
 }
 
 pub fn key_selection_dialog(app: &mut App, frame: &mut Frame) {
     let area = frame.size();
 
-    let mut items: Vec<String> = app.state.data.keys().cloned().collect();
-    items.sort();
+    let items: Vec<String> = app
+        .state
+        .sorted_keys()
+        .into_iter()
+        .map(|key| {
+            let mark = if app.state.marked_keys.contains(&key) {
+                "[x]"
+            } else {
+                "[ ]"
+            };
+            format!("{mark} {key}")
+        })
+        .collect();
     let list = List::new(items)
-        .block(Block::default().title("Select key").borders(Borders::ALL))
+        .block(
+            Block::default()
+                .title("Select key (space to mark several)")
+                .borders(Borders::ALL),
+        )
         .highlight_style(Style::new().add_modifier(Modifier::REVERSED))
         .highlight_symbol(">>")
         .repeat_highlight_symbol(true);
@@ -75,22 +409,217 @@ pub fn key_selection_dialog(app: &mut App, frame: &mut Frame) {
     frame.render_stateful_widget(list, area, &mut app.state.selection_list_state);
 }
 
+/// Formats a value compactly, switching to scientific notation for very small and large values
+pub fn format_value(val: f64) -> String {
+    if val == 0.0 || (1e-3..1e5).contains(&val.abs()) {
+        format!("{val:.4}")
+    } else {
+        format!("{val:.3e}")
+    }
+}
+
+fn tile(
+    key: &str,
+    data: &Timeseries,
+    smoothing: &Smoothing,
+    log_scale: bool,
+    selected: bool,
+    frame: &mut Frame,
+    area: Rect,
+) {
+    let (first_t, last_t) = data.get_t_bounds().unwrap_or((0.0, 0.0));
+    let last_val = data.get_data().last().map(|(_, val)| *val).unwrap_or(0.0);
+
+    let border_style = if selected {
+        Style::new().yellow().add_modifier(Modifier::BOLD)
+    } else {
+        Style::new().white()
+    };
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .border_style(border_style)
+        .title(key.to_owned())
+        .title(
+            Title::from(format!(
+                "last {} min {} max {}",
+                format_value(last_val),
+                format_value(data.get_min()),
+                format_value(data.get_max())
+            ))
+            .position(Position::Bottom),
+        );
+
+    let scale = if log_scale {
+        YScale::log_for(data.get_data().iter().map(|(_, val)| val))
+    } else {
+        YScale::Linear
+    };
+    let scaled = scale.apply_all(&data.smoothed(smoothing));
+    let dataset = Dataset::default()
+        .data(&scaled)
+        .marker(Marker::Braille)
+        .graph_type(GraphType::Line)
+        .red();
+
+    let y_bounds = [scale.apply(data.get_min()), scale.apply(data.get_max())];
+    frame.render_widget(
+        Chart::new(vec![dataset])
+            .block(block)
+            .x_axis(Axis::default().bounds([first_t, last_t]))
+            .y_axis(Axis::default().bounds(y_bounds)),
+        area,
+    );
+}
+
+pub fn grid(app: &mut App, frame: &mut Frame) {
+    let [grid_area, status_area] = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Min(0), Constraint::Length(1)])
+        .areas(frame.size());
+
+    let columns = usize::max((grid_area.width / TILE_MIN_WIDTH) as usize, 1);
+    let rows = usize::max((grid_area.height / TILE_MIN_HEIGHT) as usize, 1);
+    let page_size = columns * rows;
+    app.state.grid_columns = columns;
+    app.state.grid_page_size = page_size;
+
+    let keys = app.state.sorted_keys();
+    let page = app.state.grid_selected / page_size;
+    let num_pages = usize::max(keys.len().div_ceil(page_size), 1);
+
+    let row_areas = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints(vec![Constraint::Ratio(1, rows as u32); rows])
+        .split(grid_area);
+    let tile_areas = row_areas.iter().flat_map(|row| {
+        Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints(vec![Constraint::Ratio(1, columns as u32); columns])
+            .split(*row)
+            .to_vec()
+    });
+
+    for ((idx, key), area) in keys
+        .iter()
+        .enumerate()
+        .skip(page * page_size)
+        .take(page_size)
+        .zip(tile_areas)
+    {
+        let Some(data) = app.state.data.get(key) else {
+            continue;
+        };
+        let log_scale = app.state.is_log_scale(key);
+        tile(
+            key,
+            data,
+            &app.state.smoothing,
+            log_scale,
+            idx == app.state.grid_selected,
+            frame,
+            area,
+        );
+    }
+
+    let mut status = vec![Span::from(format!(
+        "Page {}/{} | arrows: select, PgUp/PgDn: page, enter: open, l: log scale, g: back",
+        page + 1,
+        num_pages
+    ))];
+    if let Some(input_status) = input_status(app) {
+        status.extend([Span::from(" | "), input_status]);
+    }
+    if app.state.finished_at.is_some() {
+        status.push(Span::from(format!(" | run finished{}", exit_note(app))).reversed());
+    }
+    if let Some(notice) = notice(app) {
+        status.extend([Span::from(" | "), notice]);
+    }
+    frame.render_widget(Paragraph::new(Line::from(status)), status_area);
+}
+
 pub fn passthrough(app: &mut App, frame: &mut Frame) {
-    let area = frame.size();
+    let [log_area, status_area] = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Min(0), Constraint::Length(1)])
+        .areas(frame.size());
 
-    let viewport_height = usize::max(area.as_size().height as usize - 1, 1);
-    let lines: Vec<_> = app
-        .state
-        .linebuf
+    let view = &mut app.state.log_view;
+    view.height = usize::max(log_area.height as usize, 1);
+    let window = view.window(&app.state.linebuf);
+    let current_match = view.current_match(&app.state.linebuf);
+    let lines: Vec<_> = window
         .iter()
-        .rev()
-        .take(viewport_height)
-        .rev()
-        .cloned()
-        .map(Line::from)
+        .filter_map(|idx| {
+            let (source, line) = app.state.linebuf.get(*idx)?;
+            let style = if Some(*idx) == current_match {
+                Style::new().black().on_yellow()
+            } else {
+                Style::new().reversed()
+            };
+            let mut spans = highlight_matches(line, view.search.as_ref(), style);
+            if *source == LineSource::Stderr {
+                spans.insert(0, "stderr ".red());
+            }
+            Some(Line::from(spans))
+        })
         .collect();
-    let paragraph = Paragraph::new(lines);
-    frame.render_widget(paragraph, area);
+    frame.render_widget(Paragraph::new(lines), log_area);
+
+    if let Some((kind, text)) = &view.prompt {
+        let prompt = match kind {
+            PromptKind::Search => format!("/{text}"),
+            PromptKind::Filter => format!("filter: {text}"),
+        };
+        let cursor_x = status_area.x + u16::min(prompt.chars().count() as u16, status_area.width);
+        frame.render_widget(Paragraph::new(prompt), status_area);
+        frame.set_cursor(cursor_x, status_area.y);
+        return;
+    }
+
+    let position = match (window.first(), window.last()) {
+        (Some(first), Some(last)) => format!(
+            "lines {}-{} of {}",
+            first + 1,
+            last + 1,
+            app.state.linebuf.len()
+        ),
+        _ => "no lines".to_owned(),
+    };
+    let mut status = vec![
+        match view.scroll {
+            None => "● following".green(),
+            Some(_) => "○ scrolled, End: follow".yellow(),
+        },
+        Span::from(format!(" | {position}")),
+    ];
+    if let Some(filter) = &view.filter {
+        status.push(Span::from(format!(" | filter: {filter}")));
+    }
+    if let Some(search) = &view.search {
+        status.push(Span::from(format!(" | search: {search}")));
+    }
+    status.push(Span::from(
+        " | j/k, PgUp/PgDn, Home/End: scroll, /: search, n/N: older/newer match, f: filter, p: back",
+    ));
+    if let Some(notice) = notice(app) {
+        status.extend([Span::from(" | "), notice]);
+    }
+    frame.render_widget(Paragraph::new(Line::from(status)), status_area);
+}
+
+/// Splits `line` into spans with the matches of `search` in `style`
+fn highlight_matches<'a>(line: &'a str, search: Option<&Regex>, style: Style) -> Vec<Span<'a>> {
+    let mut spans = Vec::new();
+    let mut last = 0;
+    let matches = search.into_iter().flat_map(|search| search.find_iter(line));
+    for found in matches.filter(|found| !found.is_empty()) {
+        spans.push(Span::from(&line[last..found.start()]));
+        spans.push(Span::styled(found.as_str(), style));
+        last = found.end();
+    }
+    spans.push(Span::from(&line[last..]));
+    spans
 }
 
 pub fn render(app: &mut App, frame: &mut Frame) {
@@ -98,5 +627,6 @@ pub fn render(app: &mut App, frame: &mut Frame) {
         UiState::Plot => plot(app, frame),
         UiState::KeySelection => key_selection_dialog(app, frame),
         UiState::Passthrough => passthrough(app, frame),
+        UiState::Grid => grid(app, frame),
     }
 }
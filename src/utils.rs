@@ -1,4 +1,7 @@
-use std::collections::VecDeque;
+use std::{
+    collections::VecDeque,
+    time::{SystemTime, UNIX_EPOCH},
+};
 
 #[derive(Debug)]
 pub struct RingBuffer<T> {
@@ -32,11 +35,79 @@ impl<T> RingBuffer<T> {
     pub fn peek(&self) -> Option<&T> {
         self.buf.front()
     }
+
+    /// Returns the value at `idx`, counted from the oldest one
+    pub fn get(&self, idx: usize) -> Option<&T> {
+        self.buf.get(idx)
+    }
+
+    pub fn len(&self) -> usize {
+        self.buf.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.buf.is_empty()
+    }
+}
+
+/// Formats `time` as RFC 3339 timestamp in UTC with milliseconds, e.g. `2024-03-01T12:30:00.250Z`
+pub fn format_timestamp(time: SystemTime) -> String {
+    let millis = time
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_millis() as i64);
+    let (days, millis) = (millis.div_euclid(86_400_000), millis.rem_euclid(86_400_000));
+    let (year, month, day) = civil_from_days(days);
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}.{:03}Z",
+        millis / 3_600_000,
+        millis / 60_000 % 60,
+        millis / 1000 % 60,
+        millis % 1000
+    )
+}
+
+/// Removes a leading timestamp as written by [`format_timestamp`] and the space after it
+pub fn strip_timestamp(line: &str) -> &str {
+    let Some((first, rest)) = line.split_once(' ') else {
+        return line;
+    };
+    let b = first.as_bytes();
+    let is_timestamp = b.len() == 24
+        && b[10] == b'T'
+        && b[23] == b'Z'
+        && b.iter().enumerate().all(|(i, c)| match i {
+            4 | 7 => *c == b'-',
+            13 | 16 => *c == b':',
+            19 => *c == b'.',
+            10 | 23 => true,
+            _ => c.is_ascii_digit(),
+        });
+    if is_timestamp {
+        rest
+    } else {
+        line
+    }
+}
+
+/// Converts days since 1970-01-01 to year, month and day of the proleptic Gregorian calendar
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month, day)
 }
 
 #[cfg(test)]
 mod tests {
-    use super::RingBuffer;
+    use std::time::Duration;
+
+    use super::*;
 
     #[test]
     fn wrap() {
@@ -59,5 +130,21 @@ mod tests {
 
         let v: Vec<_> = buffer.iter().collect();
         assert_eq!(v, vec![&2, &3, &4]);
+        assert_eq!(buffer.len(), 3);
+        assert_eq!(buffer.get(0), Some(&2));
+        assert_eq!(buffer.get(3), None);
+    }
+
+    #[test]
+    fn timestamps() {
+        assert_eq!(format_timestamp(UNIX_EPOCH), "1970-01-01T00:00:00.000Z");
+        let time = UNIX_EPOCH + Duration::from_millis(1_709_296_200_250);
+        assert_eq!(format_timestamp(time), "2024-03-01T12:30:00.250Z");
+        let time = UNIX_EPOCH + Duration::from_secs(951_782_400);
+        assert_eq!(format_timestamp(time), "2000-02-29T00:00:00.000Z");
+
+        assert_eq!(strip_timestamp("2024-03-01T12:30:00.250Z loss 1"), "loss 1");
+        assert_eq!(strip_timestamp("2024-03-01 loss 1"), "2024-03-01 loss 1");
+        assert_eq!(strip_timestamp("loss 1"), "loss 1");
     }
 }
@@ -0,0 +1,138 @@
+/// Smallest visible fraction of the whole step range
+const MIN_ZOOM: f64 = 1e-3;
+
+/// Visible part of the step axis
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum StepView {
+    /// Everything from the first to the last step
+    #[default]
+    Full,
+    /// Fixed window of steps
+    Window { start: f64, end: f64 },
+    /// Always the most recent `width` steps
+    Tail { width: f64 },
+}
+
+impl StepView {
+    /// Returns the visible range for data spanning `first..=last`
+    pub fn range(&self, first: f64, last: f64) -> (f64, f64) {
+        match *self {
+            StepView::Full => (first, last),
+            StepView::Window { start, end } => (start, end),
+            StepView::Tail { width } => (f64::max(last - width, first), last),
+        }
+    }
+
+    /// Scales the visible range around its center, `factor < 1` zooms in
+    pub fn zoom(&self, factor: f64, first: f64, last: f64) -> Self {
+        let full_width = last - first;
+        let (start, end) = self.range(first, last);
+        let width = f64::max((end - start) * factor, full_width * MIN_ZOOM);
+        if width >= full_width {
+            return StepView::Full;
+        }
+
+        match self {
+            StepView::Tail { .. } => StepView::Tail { width },
+            _ => {
+                let center = (start + end) / 2.0;
+                Self::clamped(center - width / 2.0, width, first, last)
+            }
+        }
+    }
+
+    /// Moves the visible range by a fraction of its width, negative values pan left
+    pub fn pan(&self, fraction: f64, first: f64, last: f64) -> Self {
+        if *self == StepView::Full {
+            return *self;
+        }
+        let (start, end) = self.range(first, last);
+        let width = end - start;
+        Self::clamped(start + fraction * width, width, first, last)
+    }
+
+    /// Follows the most recent steps, keeping the current width
+    ///
+    /// From the full view a quarter of the whole range is shown.
+    pub fn tail(&self, first: f64, last: f64) -> Self {
+        let width = if *self == StepView::Full {
+            (last - first) / 4.0
+        } else {
+            let (start, end) = self.range(first, last);
+            end - start
+        };
+        StepView::Tail { width }
+    }
+
+    fn clamped(start: f64, width: f64, first: f64, last: f64) -> Self {
+        let start = start.clamp(first, f64::max(last - width, first));
+        StepView::Window {
+            start,
+            end: start + width,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn zoom() {
+        let view = StepView::Full.zoom(0.5, 0.0, 100.0);
+        assert_eq!(
+            view,
+            StepView::Window {
+                start: 25.0,
+                end: 75.0
+            }
+        );
+
+        let view = view.zoom(2.0, 0.0, 100.0);
+        assert_eq!(view, StepView::Full);
+
+        let view = StepView::Tail { width: 50.0 }.zoom(0.5, 0.0, 100.0);
+        assert_eq!(view, StepView::Tail { width: 25.0 });
+        assert_eq!(view.range(0.0, 200.0), (175.0, 200.0));
+    }
+
+    #[test]
+    fn pan() {
+        let view = StepView::Window {
+            start: 25.0,
+            end: 75.0,
+        };
+        assert_eq!(
+            view.pan(-0.25, 0.0, 100.0),
+            StepView::Window {
+                start: 12.5,
+                end: 62.5
+            }
+        );
+        assert_eq!(
+            view.pan(1.0, 0.0, 100.0),
+            StepView::Window {
+                start: 50.0,
+                end: 100.0
+            }
+        );
+        assert_eq!(StepView::Full.pan(0.25, 0.0, 100.0), StepView::Full);
+
+        let view = StepView::Tail { width: 20.0 }.pan(-0.5, 0.0, 100.0);
+        assert_eq!(
+            view,
+            StepView::Window {
+                start: 70.0,
+                end: 90.0
+            }
+        );
+    }
+
+    #[test]
+    fn tail() {
+        let view = StepView::Full.tail(0.0, 100.0);
+        assert_eq!(view, StepView::Tail { width: 25.0 });
+        assert_eq!(view.range(0.0, 1000.0), (975.0, 1000.0));
+        assert_eq!(view.range(0.0, 10.0), (0.0, 10.0));
+    }
+}